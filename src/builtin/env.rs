@@ -1,80 +1,221 @@
 use crate::builtin::ir::{
-    eval_number, get_input, load_llvm_value, parse_list_of_floats,
+    compare, const_number, convert_number, expect_numbers, fold_llvm_values, number_type,
+    single_argument,
 };
+use crate::cli::Target;
 use crate::ir::block::append_basic_block_in_context;
-use crate::ir::llvm_type::{function_type, int32_type};
+use crate::ir::context::{Builder, Context, Module};
+use crate::ir::diagnostic::{capture_errors, take_message};
+use crate::ir::llvm_type::{double_type, function_type, int32_type, int64_type};
 use crate::ir::operate::build_position_at_end;
+use crate::ir::scope::{declare_function, declare_global};
 use crate::{add_function, create_input_fn, create_printf, RispErr, RispExp};
+use llvm_sys::bit_reader::LLVMParseBitcodeInContext2;
+use llvm_sys::bit_writer::LLVMWriteBitcodeToFile;
 use llvm_sys::core::{
-    LLVMBuildAdd, LLVMBuildSub, LLVMContextCreate, LLVMContextDispose, LLVMCreateBuilderInContext,
-    LLVMDisposeBuilder, LLVMDumpModule, LLVMModuleCreateWithName, LLVMPrintModuleToFile,
+    LLVMBuildAdd, LLVMBuildFAdd, LLVMBuildFDiv, LLVMBuildFMul, LLVMBuildFSub, LLVMBuildMul,
+    LLVMBuildSDiv, LLVMBuildSub, LLVMCreateMemoryBufferWithContentsOfFile, LLVMDisposeMemoryBuffer,
+    LLVMDumpModule, LLVMPrintModuleToFile,
 };
-use llvm_sys::prelude::{LLVMBuilderRef, LLVMContextRef, LLVMModuleRef};
+use llvm_sys::linker::LLVMLinkModules2;
+use llvm_sys::LLVMIntPredicate::{LLVMIntEQ, LLVMIntSGE, LLVMIntSGT, LLVMIntSLE, LLVMIntSLT};
+use llvm_sys::LLVMRealPredicate::{
+    LLVMRealOEQ, LLVMRealOGE, LLVMRealOGT, LLVMRealOLE, LLVMRealOLT,
+};
+
 use llvm_sys::{LLVMBasicBlock, LLVMValue};
 use std::collections::HashMap;
+use std::ptr::null_mut;
 
-#[derive(Clone)]
-pub struct RispEnv {
+// owns the module being generated and the builder writing it, neither is shared
+pub struct RispEnv<'ctx> {
     pub data: HashMap<String, RispExp>,
-    pub llvm_context: LLVMContextRef,
-    pub llvm_module: LLVMModuleRef,
-    pub llvm_builder: LLVMBuilderRef,
+    // innermost last, only populated while compiling a function body
+    pub scopes: Vec<HashMap<String, RispExp>>,
+    pub llvm_context: &'ctx Context,
+    pub llvm_module: Module<'ctx>,
+    pub llvm_builder: Builder<'ctx>,
     pub built_ins: HashMap<&'static str, *mut LLVMValue>,
+    // decides how `printf` and `input` are lowered
+    pub target: Target,
+    // constant expressions are folded before code generation
+    pub fold: bool,
+    // generated modules go through the LLVM verifier
+    pub verify: bool,
 }
 
-impl RispEnv {
-    unsafe fn new(data: HashMap<String, RispExp>) -> Self {
-        let llvm_context = LLVMContextCreate();
+impl<'ctx> RispEnv<'ctx> {
+    fn new(data: HashMap<String, RispExp>, llvm_context: &'ctx Context) -> Self {
         let mut env = RispEnv {
             data,
+            scopes: vec![],
             llvm_context,
-            llvm_module: LLVMModuleCreateWithName(c_str!("main_module")),
-            llvm_builder: LLVMCreateBuilderInContext(llvm_context),
+            llvm_module: Module::new(llvm_context, "main_module"),
+            llvm_builder: Builder::new(llvm_context),
             built_ins: HashMap::new(),
+            target: Target::Host,
+            fold: true,
+            verify: true,
         };
         env.setup_builtin();
-        let (_, _) = env.setup_main();
         env
     }
 
+    // continues in a fresh module that declares the globals and functions bound so far,
+    // returns the previous module for whoever runs it
+    pub fn begin_module(&mut self, name: &str) -> Module<'ctx> {
+        let module = Module::new(self.llvm_context, name);
+        for exp in self.data.values_mut() {
+            match exp {
+                RispExp::Variable(global) => *global = declare_global(&module, *global),
+                RispExp::Lambda(_, _, function) => *function = declare_function(&module, *function),
+                _ => {}
+            }
+        }
+        let previous = std::mem::replace(&mut self.llvm_module, module);
+        self.setup_builtin();
+        previous
+    }
+
+    // the generated module, for when nothing else will be compiled
+    pub fn into_module(self) -> Module<'ctx> {
+        self.llvm_module
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&RispExp> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.data.get(name))
+    }
+
+    pub fn lookup_mut(&mut self, name: &str) -> Option<&mut RispExp> {
+        match self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find(|scope| scope.contains_key(name))
+        {
+            Some(scope) => scope.get_mut(name),
+            None => self.data.get_mut(name),
+        }
+    }
+
+    pub fn bind(&mut self, name: &str, exp: RispExp) {
+        match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.to_string(), exp),
+            None => self.data.insert(name.to_string(), exp),
+        };
+    }
+
     #[allow(dead_code)]
     pub fn setup_builtin(&mut self) {
         // print function
-        self.built_ins
-            .insert("printf", create_printf(self.llvm_module));
-        self.built_ins
-            .insert("input", create_input_fn(self.llvm_module));
+        self.built_ins.insert(
+            "printf",
+            create_printf(self.llvm_context, &self.llvm_module),
+        );
+        self.built_ins.insert(
+            "input",
+            create_input_fn(self.llvm_context, &self.llvm_module),
+        );
     }
 
     #[allow(dead_code)]
     pub fn dump(&self) {
-        unsafe { LLVMDumpModule(self.llvm_module) }
+        unsafe { LLVMDumpModule(self.llvm_module.as_raw()) }
     }
 
-    #[allow(dead_code)]
-    pub fn emit_file(&self, path: &str) {
-        let mut error: *mut i8 = 0 as *mut i8;
+    pub fn emit_file(&self, path: &str) -> Result<(), RispErr> {
+        let mut error: *mut i8 = null_mut();
         let buf: *mut *mut i8 = &mut error;
         let result = unsafe {
-            LLVMPrintModuleToFile(self.llvm_module, c_string!(path).as_ptr() as *const _, buf)
+            LLVMPrintModuleToFile(
+                self.llvm_module.as_raw(),
+                c_string!(path).as_ptr() as *const _,
+                buf,
+            )
         };
 
         if result > 0 {
-            println!("{}", string_from_raw!(error));
+            return Err(RispErr::Reason(take_message(error)));
         }
+        Ok(())
+    }
+
+    pub fn emit_bitcode(&self, path: &str) -> Result<(), RispErr> {
+        if unsafe { LLVMWriteBitcodeToFile(self.llvm_module.as_raw(), c_string!(path).as_ptr()) }
+            != 0
+        {
+            return Err(RispErr::Reason(format!(
+                "cannot write bitcode to `{}`",
+                path
+            )));
+        }
+        Ok(())
+    }
+
+    // links in a module loaded with `load_bitcode`, definitions may not clash
+    pub fn link_bitcode(&mut self, path: &str) -> Result<(), RispErr> {
+        let module = load_bitcode(self.llvm_context, path)?.into_raw();
+        // consumes `module`
+        let (failed, errors) = capture_errors(self.llvm_context, || unsafe {
+            LLVMLinkModules2(self.llvm_module.as_raw(), module)
+        });
+        if failed != 0 {
+            return Err(RispErr::Reason(format!(
+                "cannot link `{}`: {}",
+                path,
+                errors.join(", ")
+            )));
+        }
+        Ok(())
     }
 
     pub fn setup_main(&mut self) -> (*mut LLVMBasicBlock, *mut LLVMValue) {
-        let fn_type = function_type(int32_type(), &mut []);
-        let main_function = add_function(self.llvm_module, fn_type, "main");
+        let fn_type = function_type(int32_type(self.llvm_context), &mut []);
+        let main_function = add_function(&self.llvm_module, fn_type, "main");
         let block = append_basic_block_in_context(self.llvm_context, main_function, "entry");
-        build_position_at_end(self.llvm_builder, block);
+        build_position_at_end(&self.llvm_builder, block);
 
         (block, main_function)
     }
 }
 
-pub fn default_env() -> RispEnv {
+// reads bitcode written by `emit_bitcode` or any other LLVM tool
+pub fn load_bitcode<'ctx>(context: &'ctx Context, path: &str) -> Result<Module<'ctx>, RispErr> {
+    let mut buffer = null_mut();
+    let mut error: *mut i8 = null_mut();
+    let path_str = c_string!(path);
+    if unsafe {
+        LLVMCreateMemoryBufferWithContentsOfFile(path_str.as_ptr(), &mut buffer, &mut error)
+    } != 0
+    {
+        return Err(RispErr::Reason(format!(
+            "cannot read `{}`: {}",
+            path,
+            take_message(error)
+        )));
+    }
+
+    let mut module = null_mut();
+    let (failed, errors) = capture_errors(context, || unsafe {
+        let failed = LLVMParseBitcodeInContext2(context.as_raw(), buffer, &mut module);
+        LLVMDisposeMemoryBuffer(buffer);
+        failed
+    });
+    if failed != 0 {
+        return Err(RispErr::Reason(format!(
+            "cannot load `{}`: {}",
+            path,
+            errors.join(", ")
+        )));
+    }
+    Ok(unsafe { Module::from_raw(context, module) })
+}
+
+pub fn default_env(context: &Context) -> RispEnv<'_> {
     let mut data: HashMap<String, RispExp> = HashMap::new();
 
     data.insert(
@@ -85,25 +226,17 @@ pub fn default_env() -> RispEnv {
              args: &[RispExp],
              _llvm_ref: Option<*mut LLVMValue>|
              -> Result<RispExp, RispErr> {
-                let floats = parse_list_of_floats(args)?;
-                let ret = floats.iter().fold(0.0, |sum, a| sum + a);
-
-                if args.len() < 2 {
-                    panic!("Missing argument!");
-                }
+                expect_numbers(args)?;
 
                 // IR
-                let (arg1, arg2) = unsafe {
-                    (
-                        load_llvm_value(env, args.get_unchecked(0)),
-                        load_llvm_value(env, args.get_unchecked(1)),
-                    )
+                let llvm_ref = match args.len() {
+                    0 => llvm_integer!(env.llvm_context, 0),
+                    _ => {
+                        fold_llvm_values(env, "+", args, (LLVMBuildAdd, LLVMBuildFAdd), "add_ret")?
+                    }
                 };
 
-                let llvm_ref =
-                    unsafe { LLVMBuildAdd(env.llvm_builder, arg1, arg2, c_str!("add_ret")) };
-
-                Ok(RispExp::Number(ret, (llvm_ref, true)))
+                Ok(RispExp::Number(llvm_ref))
             },
         ),
     );
@@ -116,71 +249,158 @@ pub fn default_env() -> RispEnv {
              args: &[RispExp],
              _llvm_ref: Option<*mut LLVMValue>|
              -> Result<RispExp, RispErr> {
-                let floats = parse_list_of_floats(args)?;
+                expect_numbers(args)?;
 
-                let first = *floats
-                    .first()
-                    .ok_or(RispErr::Reason("expected at least on number".to_string()))?;
+                // IR
+                let llvm_ref = match args.len() {
+                    0 => {
+                        return Err(RispErr::Reason(
+                            "`-` expects at least 1 argument".to_string(),
+                        ))
+                    }
+                    1 => {
+                        let zero = const_number(number_type(env.llvm_context, &args[0]), 0.0);
+                        let operands = [RispExp::Number(zero), args[0].clone()];
+                        fold_llvm_values(
+                            env,
+                            "-",
+                            &operands,
+                            (LLVMBuildSub, LLVMBuildFSub),
+                            "neg_ret",
+                        )?
+                    }
+                    _ => {
+                        fold_llvm_values(env, "-", args, (LLVMBuildSub, LLVMBuildFSub), "sub_ret")?
+                    }
+                };
 
-                let s_rest = floats[1..].iter().fold(0.0, |sum, a| sum + a);
-                let ret = first - s_rest;
+                Ok(RispExp::Number(llvm_ref))
+            },
+        ),
+    );
 
-                if args.len() < 2 {
-                    panic!("Missing argument!");
-                }
+    data.insert(
+        "*".to_string(),
+        RispExp::Func(
+            "*".to_string(),
+            |env: &RispEnv,
+             args: &[RispExp],
+             _llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                expect_numbers(args)?;
 
                 // IR
-                let (arg1, arg2) = unsafe {
-                    (
-                        load_llvm_value(env, args.get_unchecked(0)),
-                        load_llvm_value(env, args.get_unchecked(1)),
-                    )
+                let llvm_ref = match args.len() {
+                    0 => llvm_integer!(env.llvm_context, 1),
+                    _ => {
+                        fold_llvm_values(env, "*", args, (LLVMBuildMul, LLVMBuildFMul), "mul_ret")?
+                    }
                 };
 
-                let llvm_ref =
-                    unsafe { LLVMBuildSub(env.llvm_builder, arg1, arg2, c_str!("sub_ret")) };
+                Ok(RispExp::Number(llvm_ref))
+            },
+        ),
+    );
+
+    data.insert(
+        "/".to_string(),
+        RispExp::Func(
+            "/".to_string(),
+            |env: &RispEnv,
+             args: &[RispExp],
+             _llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                expect_numbers(args)?;
 
-                // let llvm_ref = build_load(env.llvm_builder, llvm_ref, "");
+                // IR
+                let llvm_ref = match args.len() {
+                    0 => {
+                        return Err(RispErr::Reason(
+                            "`/` expects at least 1 argument".to_string(),
+                        ))
+                    }
+                    1 => {
+                        let one = const_number(number_type(env.llvm_context, &args[0]), 1.0);
+                        let operands = [RispExp::Number(one), args[0].clone()];
+                        fold_llvm_values(
+                            env,
+                            "/",
+                            &operands,
+                            (LLVMBuildSDiv, LLVMBuildFDiv),
+                            "div_ret",
+                        )?
+                    }
+                    _ => {
+                        fold_llvm_values(env, "/", args, (LLVMBuildSDiv, LLVMBuildFDiv), "div_ret")?
+                    }
+                };
 
-                Ok(RispExp::Number(ret, (llvm_ref, true)))
+                Ok(RispExp::Number(llvm_ref))
             },
         ),
     );
 
     data.insert(
-        "*".to_string(),
+        "=".to_string(),
         RispExp::Func(
-            "*".to_string(),
+            "=".to_string(),
             |env: &RispEnv,
              args: &[RispExp],
-             llvm_ref: Option<*mut LLVMValue>|
+             _llvm_ref: Option<*mut LLVMValue>|
              -> Result<RispExp, RispErr> {
-                let floats = parse_list_of_floats(args)?;
-                let ret = floats.iter().fold(1.0, |sum, a| sum * a);
+                compare(env, args, (LLVMIntEQ, LLVMRealOEQ))
+            },
+        ),
+    );
 
-                Ok(match llvm_ref {
-                    Some(l_ref) => RispExp::Number(ret, (l_ref, false)),
-                    None => eval_number(env, ret),
-                })
+    data.insert(
+        "<".to_string(),
+        RispExp::Func(
+            "<".to_string(),
+            |env: &RispEnv,
+             args: &[RispExp],
+             _llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                compare(env, args, (LLVMIntSLT, LLVMRealOLT))
             },
         ),
     );
 
     data.insert(
-        "/".to_string(),
+        ">".to_string(),
         RispExp::Func(
-            "/".to_string(),
+            ">".to_string(),
             |env: &RispEnv,
              args: &[RispExp],
-             llvm_ref: Option<*mut LLVMValue>|
+             _llvm_ref: Option<*mut LLVMValue>|
              -> Result<RispExp, RispErr> {
-                let floats = parse_list_of_floats(args)?;
-                let ret = floats.iter().fold(1.0, |sum, a| sum / a);
+                compare(env, args, (LLVMIntSGT, LLVMRealOGT))
+            },
+        ),
+    );
+
+    data.insert(
+        "<=".to_string(),
+        RispExp::Func(
+            "<=".to_string(),
+            |env: &RispEnv,
+             args: &[RispExp],
+             _llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                compare(env, args, (LLVMIntSLE, LLVMRealOLE))
+            },
+        ),
+    );
 
-                Ok(match llvm_ref {
-                    Some(l_ref) => RispExp::Number(ret, (l_ref, false)),
-                    None => eval_number(env, ret),
-                })
+    data.insert(
+        ">=".to_string(),
+        RispExp::Func(
+            ">=".to_string(),
+            |env: &RispEnv,
+             args: &[RispExp],
+             _llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                compare(env, args, (LLVMIntSGE, LLVMRealOGE))
             },
         ),
     );
@@ -193,13 +413,7 @@ pub fn default_env() -> RispEnv {
              args: &[RispExp],
              _llvm_ref: Option<*mut LLVMValue>|
              -> Result<RispExp, RispErr> {
-                Ok(match args.first() {
-                    Some(arg) => {
-                        println!("{}", arg);
-                        arg.clone()
-                    }
-                    _ => RispExp::Null
-                })
+                Ok(args.first().cloned().unwrap_or(RispExp::Null))
             },
         ),
     );
@@ -212,24 +426,80 @@ pub fn default_env() -> RispEnv {
              _args: &[RispExp],
              llvm_ref: Option<*mut LLVMValue>|
              -> Result<RispExp, RispErr> {
-                let input = get_input("Type something...");
+                // the value is only known once scanf has run
+                Ok(RispExp::Number(llvm_ref.unwrap()))
+            },
+        ),
+    );
 
-                Ok(match input.parse::<f64>() {
-                    Ok(f) => RispExp::Number(f, (llvm_ref.unwrap(), false)),
-                    Err(_) => RispExp::Null,
-                })
+    data.insert(
+        "input-float".to_string(),
+        RispExp::Func(
+            "input-float".to_string(),
+            |_env: &RispEnv,
+             _args: &[RispExp],
+             llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                // the value is only known once scanf has run
+                Ok(RispExp::Number(llvm_ref.unwrap()))
             },
         ),
     );
 
-    unsafe { RispEnv::new(data) }
-}
+    data.insert(
+        "float".to_string(),
+        RispExp::Func(
+            "float".to_string(),
+            |env: &RispEnv,
+             args: &[RispExp],
+             _llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                let value = single_argument("float", args)?;
 
-impl Drop for RispEnv {
-    fn drop(&mut self) {
-        unsafe {
-            LLVMDisposeBuilder(self.llvm_builder);
-            LLVMContextDispose(self.llvm_context);
-        }
-    }
+                // IR
+                let llvm_ref =
+                    convert_number(env, value, double_type(env.llvm_context), "float_ret");
+
+                Ok(RispExp::Number(llvm_ref))
+            },
+        ),
+    );
+
+    data.insert(
+        "int".to_string(),
+        RispExp::Func(
+            "int".to_string(),
+            |env: &RispEnv,
+             args: &[RispExp],
+             _llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                let value = single_argument("int", args)?;
+
+                // IR, truncates toward zero like the Rust cast
+                let llvm_ref = convert_number(env, value, int32_type(env.llvm_context), "int_ret");
+
+                Ok(RispExp::Number(llvm_ref))
+            },
+        ),
+    );
+
+    data.insert(
+        "long".to_string(),
+        RispExp::Func(
+            "long".to_string(),
+            |env: &RispEnv,
+             args: &[RispExp],
+             _llvm_ref: Option<*mut LLVMValue>|
+             -> Result<RispExp, RispErr> {
+                let value = single_argument("long", args)?;
+
+                // IR
+                let llvm_ref = convert_number(env, value, int64_type(env.llvm_context), "long_ret");
+
+                Ok(RispExp::Number(llvm_ref))
+            },
+        ),
+    );
+
+    RispEnv::new(data, context)
 }
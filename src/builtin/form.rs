@@ -0,0 +1,527 @@
+use crate::builtin::env::RispEnv;
+use crate::builtin::ir::{const_number, llvm_value, number_type};
+use crate::infer::types::Type;
+use crate::ir::block::{append_basic_block_in_context, get_basic_block_parent, get_insert_block};
+use crate::ir::context::Context;
+use crate::ir::llvm_type::{
+    element_type, function_type, int1_type, int32_type, is_double_type, type_of,
+};
+use crate::ir::operate::{
+    add_function, add_incoming, build_br, build_cond_br, build_entry_alloca, build_fcmp,
+    build_icmp, build_phi, build_position_at_end, build_ret, build_store, get_param, set_tail_call,
+};
+use crate::ir::scope::{add_global, get_named_global, set_initializer};
+use crate::{eval, RispErr, RispExp};
+use llvm_sys::LLVMIntPredicate::LLVMIntNE;
+use llvm_sys::LLVMRealPredicate::LLVMRealONE;
+use llvm_sys::{LLVMBasicBlock, LLVMType, LLVMValue};
+use std::collections::HashMap;
+
+// special forms receive their arguments unevaluated
+pub fn eval_special_form(
+    env: &mut RispEnv,
+    name: &str,
+    arg_forms: &[RispExp],
+) -> Option<Result<RispExp, RispErr>> {
+    match name {
+        "define" => Some(eval_define(env, arg_forms)),
+        "defn" => Some(eval_defn(env, arg_forms)),
+        "lambda" => Some(eval_lambda(env, arg_forms)),
+        "if" => Some(eval_if(env, arg_forms)),
+        "cond" => Some(cond_to_if(arg_forms).and_then(|exp| eval(&exp, env))),
+        "while" => Some(eval_while(env, arg_forms)),
+        "set!" => Some(eval_set(env, arg_forms)),
+        _ => None,
+    }
+}
+
+// (define name expr)
+pub fn eval_define(env: &mut RispEnv, arg_forms: &[RispExp]) -> Result<RispExp, RispErr> {
+    let (name, value_form) = match arg_forms {
+        [RispExp::Symbol(name), value_form] => (name, value_form),
+        _ => return Err(RispErr::Reason("expected (define name expr)".to_string())),
+    };
+
+    if let Some(RispExp::Func(_, _)) = env.lookup(name) {
+        return Err(RispErr::Reason(format!(
+            "cannot redefine builtin `{}`",
+            name
+        )));
+    }
+
+    let value = eval(value_form, env)?;
+    match value {
+        RispExp::Number(_) => {}
+        RispExp::Lambda(_, _, _) => {
+            env.bind(name, value.clone());
+            return Ok(value);
+        }
+        _ => {
+            return Err(RispErr::Reason(format!(
+                "`{}` must be bound to a number",
+                name
+            )))
+        }
+    };
+
+    let llvm_type = number_type(env.llvm_context, &value);
+
+    // inside a function body the binding is a local variable
+    if !env.scopes.is_empty() {
+        let slot = build_entry_alloca(&env.llvm_builder, llvm_type, name);
+        build_store(&env.llvm_builder, llvm_value(&value), slot);
+
+        env.bind(name, RispExp::Variable(slot));
+        return Ok(value);
+    }
+
+    // a redefinition reuses the global emitted by the first one
+    let global = match get_named_global(&env.llvm_module, name) {
+        Some(global) if element_type(type_of(global)) != llvm_type => {
+            return Err(RispErr::Reason(format!(
+                "`{}` was defined with a different type",
+                name
+            )))
+        }
+        Some(global) => global,
+        None => {
+            let global = add_global(&env.llvm_module, llvm_type, name);
+            set_initializer(global, const_number(llvm_type, 0.0));
+            global
+        }
+    };
+    build_store(&env.llvm_builder, llvm_value(&value), global);
+    env.bind(name, RispExp::Variable(global));
+
+    Ok(value)
+}
+
+// (set! name expr) stores into an existing variable
+pub fn eval_set(env: &mut RispEnv, arg_forms: &[RispExp]) -> Result<RispExp, RispErr> {
+    let (name, value_form) = match arg_forms {
+        [RispExp::Symbol(name), value_form] => (name, value_form),
+        _ => return Err(RispErr::Reason("expected (set! name expr)".to_string())),
+    };
+
+    let slot = match env.lookup(name) {
+        Some(RispExp::Variable(slot)) => *slot,
+        Some(_) => return Err(RispErr::Reason(format!("cannot assign to `{}`", name))),
+        None => return Err(RispErr::Reason(format!("unexpected symbol k='{}'", name))),
+    };
+
+    let value = eval(value_form, env)?;
+    if !matches!(value, RispExp::Number(_)) {
+        return Err(RispErr::Reason(format!(
+            "`{}` must be assigned a number",
+            name
+        )));
+    }
+    if element_type(type_of(slot)) != number_type(env.llvm_context, &value) {
+        return Err(RispErr::Reason(format!(
+            "`{}` must keep the type it was defined with",
+            name
+        )));
+    }
+    build_store(&env.llvm_builder, llvm_value(&value), slot);
+
+    Ok(value)
+}
+
+// (while test body...) evaluates to null
+pub fn eval_while(env: &mut RispEnv, arg_forms: &[RispExp]) -> Result<RispExp, RispErr> {
+    let (test_form, body) = arg_forms
+        .split_first()
+        .ok_or(RispErr::Reason("expected (while test body...)".to_string()))?;
+
+    let function = get_basic_block_parent(get_insert_block(&env.llvm_builder));
+    let header_block = append_basic_block_in_context(env.llvm_context, function, "loop_header");
+    let body_block = append_basic_block_in_context(env.llvm_context, function, "loop_body");
+    let exit_block = append_basic_block_in_context(env.llvm_context, function, "loop_exit");
+    build_br(&env.llvm_builder, header_block);
+
+    // the test is re-evaluated on every iteration
+    build_position_at_end(&env.llvm_builder, header_block);
+    let condition = eval_condition(env, test_form, "while")?;
+    build_cond_br(&env.llvm_builder, condition, body_block, exit_block);
+
+    build_position_at_end(&env.llvm_builder, body_block);
+    for form in body.iter() {
+        eval(form, env)?;
+    }
+    build_br(&env.llvm_builder, header_block);
+
+    build_position_at_end(&env.llvm_builder, exit_block);
+
+    Ok(RispExp::Null)
+}
+
+// (defn name (params...) body...)
+pub fn eval_defn(env: &mut RispEnv, arg_forms: &[RispExp]) -> Result<RispExp, RispErr> {
+    match arg_forms {
+        [RispExp::Symbol(name), RispExp::List(params), body @ ..] if !body.is_empty() => {
+            if let Some(RispExp::Func(_, _)) = env.lookup(name) {
+                return Err(RispErr::Reason(format!(
+                    "cannot redefine builtin `{}`",
+                    name
+                )));
+            }
+            compile_function(env, Some(name), params, body)
+        }
+        _ => Err(RispErr::Reason(
+            "expected (defn name (params...) body...)".to_string(),
+        )),
+    }
+}
+
+// (lambda (params...) body...)
+pub fn eval_lambda(env: &mut RispEnv, arg_forms: &[RispExp]) -> Result<RispExp, RispErr> {
+    match arg_forms {
+        [RispExp::List(params), body @ ..] if !body.is_empty() => {
+            compile_function(env, None, params, body)
+        }
+        _ => Err(RispErr::Reason(
+            "expected (lambda (params...) body...)".to_string(),
+        )),
+    }
+}
+
+// (if test then else?), the missing else branch yields 0
+pub fn eval_if(env: &mut RispEnv, arg_forms: &[RispExp]) -> Result<RispExp, RispErr> {
+    let (test_form, then_form, else_form) = match arg_forms {
+        [test_form, then_form] => (test_form, then_form, &RispExp::Null),
+        [test_form, then_form, else_form] => (test_form, then_form, else_form),
+        _ => return Err(RispErr::Reason("expected (if test then else?)".to_string())),
+    };
+
+    let condition = eval_condition(env, test_form, "if")?;
+
+    let function = get_basic_block_parent(get_insert_block(&env.llvm_builder));
+    let then_block = append_basic_block_in_context(env.llvm_context, function, "then");
+    let else_block = append_basic_block_in_context(env.llvm_context, function, "else");
+    let merge_block = append_basic_block_in_context(env.llvm_context, function, "merge");
+    build_cond_br(&env.llvm_builder, condition, then_block, else_block);
+
+    // nested forms may leave the builder in a later block than the one we started
+    build_position_at_end(&env.llvm_builder, then_block);
+    let then_exp = eval(then_form, env)?;
+    let then_value = branch_value(&then_exp);
+    let then_end = get_insert_block(&env.llvm_builder);
+    build_br(&env.llvm_builder, merge_block);
+
+    build_position_at_end(&env.llvm_builder, else_block);
+    let else_exp = eval(else_form, env)?;
+    let else_value = branch_value(&else_exp);
+    let else_end = get_insert_block(&env.llvm_builder);
+    build_br(&env.llvm_builder, merge_block);
+
+    // a branch without a number yields zero of the other branch's type
+    let phi_type = match (then_value, else_value) {
+        (Some(_), Some(_))
+            if number_type(env.llvm_context, &then_exp)
+                != number_type(env.llvm_context, &else_exp) =>
+        {
+            return Err(RispErr::Reason(
+                "`if` branches must both be integers or both be floats".to_string(),
+            ))
+        }
+        (Some(_), _) => number_type(env.llvm_context, &then_exp),
+        (_, _) => number_type(env.llvm_context, &else_exp),
+    };
+    let then_value = then_value.unwrap_or_else(|| const_number(phi_type, 0.0));
+    let else_value = else_value.unwrap_or_else(|| const_number(phi_type, 0.0));
+
+    build_position_at_end(&env.llvm_builder, merge_block);
+    let phi = build_phi(&env.llvm_builder, phi_type, "if_ret");
+    add_incoming(phi, vec![then_value, else_value], vec![then_end, else_end]);
+
+    Ok(RispExp::Number(phi))
+}
+
+fn branch_value(exp: &RispExp) -> Option<*mut LLVMValue> {
+    match exp {
+        RispExp::Number(value) => Some(*value),
+        _ => None,
+    }
+}
+
+// evaluates a test form to an i1 that is set when it is non-zero
+fn eval_condition(
+    env: &mut RispEnv,
+    test_form: &RispExp,
+    form_name: &str,
+) -> Result<*mut LLVMValue, RispErr> {
+    let test = eval(test_form, env)?;
+    if !matches!(test, RispExp::Number(_)) {
+        return Err(RispErr::Reason(format!(
+            "`{}` expects a number, got {}",
+            form_name, test
+        )));
+    }
+    let test_value = llvm_value(&test);
+    let test_type = number_type(env.llvm_context, &test);
+    let name = format!("{}_cond", form_name);
+    let condition = if test_type == int1_type(env.llvm_context) {
+        test_value
+    } else if is_double_type(test_type) {
+        let zero = const_number(test_type, 0.0);
+        build_fcmp(&env.llvm_builder, LLVMRealONE, test_value, zero, &name)
+    } else {
+        build_icmp(
+            &env.llvm_builder,
+            LLVMIntNE,
+            test_value,
+            llvm_integer!(env.llvm_context, 0),
+            &name,
+        )
+    };
+
+    Ok(condition)
+}
+
+// (cond (test expr) ... (else expr)) is rewritten into nested `if`s
+pub fn cond_to_if(clauses: &[RispExp]) -> Result<RispExp, RispErr> {
+    let (clause, rest) = match clauses.split_first() {
+        Some((RispExp::List(clause), rest)) => (clause, rest),
+        Some((clause, _)) => {
+            return Err(RispErr::Reason(format!(
+                "expected (test expr) clause, got {}",
+                clause
+            )))
+        }
+        None => return Ok(RispExp::Null),
+    };
+
+    match &clause[..] {
+        [RispExp::Symbol(s), expr] if s == "else" => {
+            if rest.is_empty() {
+                Ok(expr.clone())
+            } else {
+                Err(RispErr::Reason(
+                    "`else` must be the last clause".to_string(),
+                ))
+            }
+        }
+        [test, expr] => Ok(RispExp::List(vec![
+            RispExp::Symbol("if".to_string()),
+            test.clone(),
+            expr.clone(),
+            cond_to_if(rest)?,
+        ])),
+        _ => Err(RispErr::Reason(format!(
+            "expected (test expr) clause, got {}",
+            RispExp::List(clause.clone())
+        ))),
+    }
+}
+
+fn compile_function(
+    env: &mut RispEnv,
+    name: Option<&String>,
+    param_forms: &[RispExp],
+    body: &[RispExp],
+) -> Result<RispExp, RispErr> {
+    // type inference annotates the signature as ((param type)...) -> type,
+    // anything left out is an i32
+    let (ret_type, body) = match body {
+        [RispExp::Symbol(arrow), RispExp::Symbol(ret), body @ ..] if arrow == "->" => {
+            (parse_type(env.llvm_context, ret)?, body)
+        }
+        _ => (int32_type(env.llvm_context), body),
+    };
+    let (last, forms) = body
+        .split_last()
+        .ok_or(RispErr::Reason("expected a function body".to_string()))?;
+    let (params, mut param_types): (Vec<String>, Vec<*mut LLVMType>) = param_forms
+        .iter()
+        .map(|param| match param {
+            RispExp::Symbol(s) => Ok((s.clone(), int32_type(env.llvm_context))),
+            RispExp::List(typed) => match &typed[..] {
+                [RispExp::Symbol(s), RispExp::Symbol(t)] => {
+                    Ok((s.clone(), parse_type(env.llvm_context, t)?))
+                }
+                _ => Err(RispErr::Reason(format!("invalid parameter {}", param))),
+            },
+            _ => Err(RispErr::Reason(format!("invalid parameter {}", param))),
+        })
+        .collect::<Result<Vec<(String, *mut LLVMType)>, RispErr>>()?
+        .into_iter()
+        .unzip();
+
+    let f_name = name.map_or("lambda", |x| &x[..]);
+    let fn_type = function_type(ret_type, &mut param_types);
+    let function = add_function(&env.llvm_module, fn_type, f_name);
+    let lambda = RispExp::Lambda(f_name.to_string(), params.len(), function);
+
+    // bound before the body is compiled so it can call itself
+    if let Some(name) = name {
+        env.bind(name, lambda.clone());
+    }
+
+    let insert_block = get_insert_block(&env.llvm_builder);
+    let block = append_basic_block_in_context(env.llvm_context, function, "entry");
+    build_position_at_end(&env.llvm_builder, block);
+
+    // locals of the enclosing function are not reachable from this one
+    let outer_scopes = std::mem::replace(&mut env.scopes, vec![HashMap::new()]);
+    if let Some(name) = name {
+        env.bind(name, lambda.clone());
+    }
+
+    // parameters live in stack slots so they can be reassigned like locals
+    let mut slots = vec![];
+    for (index, param) in params.iter().enumerate() {
+        let slot = build_entry_alloca(&env.llvm_builder, param_types[index], param);
+        build_store(&env.llvm_builder, get_param(function, index), slot);
+        env.bind(param, RispExp::Variable(slot));
+        slots.push(slot);
+    }
+
+    // self tail calls jump back here after storing the new arguments
+    let body_block = append_basic_block_in_context(env.llvm_context, function, "body");
+    build_br(&env.llvm_builder, body_block);
+    build_position_at_end(&env.llvm_builder, body_block);
+
+    let tail = TailContext {
+        function,
+        slots,
+        body_block,
+        ret_type,
+    };
+    let result = forms
+        .iter()
+        .try_for_each(|form| eval(form, env).map(|_| ()))
+        .and_then(|_| eval_tail(env, last, &tail));
+
+    env.scopes = outer_scopes;
+    build_position_at_end(&env.llvm_builder, insert_block);
+
+    result.map(|_| lambda)
+}
+
+// the function whose body is being compiled
+struct TailContext {
+    function: *mut LLVMValue,
+    slots: Vec<*mut LLVMValue>,
+    body_block: *mut LLVMBasicBlock,
+    ret_type: *mut LLVMType,
+}
+
+fn parse_type(context: &Context, name: &str) -> Result<*mut LLVMType, RispErr> {
+    Type::from_name(name)
+        .map(|t| t.llvm_type(context))
+        .ok_or(RispErr::Reason(format!("unknown type `{}`", name)))
+}
+
+// compiles a form in tail position, terminating every path with a `ret` or,
+// for calls back into the same function, a jump to the top of its body
+fn eval_tail(env: &mut RispEnv, exp: &RispExp, tail: &TailContext) -> Result<(), RispErr> {
+    if let RispExp::List(list) = exp {
+        match list.split_first() {
+            Some((RispExp::Symbol(s), arg_forms)) if s == "if" => {
+                return eval_tail_if(env, arg_forms, tail)
+            }
+            Some((RispExp::Symbol(s), arg_forms)) if s == "cond" => {
+                return eval_tail(env, &cond_to_if(arg_forms)?, tail)
+            }
+            Some((RispExp::Symbol(s), arg_forms)) => match env.lookup(s) {
+                Some(RispExp::Lambda(_, _, function)) if *function == tail.function => {
+                    return eval_self_tail_call(env, s, arg_forms, tail)
+                }
+                Some(RispExp::Lambda(_, _, _)) => {
+                    let exp = eval(exp, env)?;
+                    if let RispExp::Number(call) = exp {
+                        set_tail_call(call);
+                    }
+                    return build_function_ret(env, &exp, tail);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    let exp = eval(exp, env)?;
+    build_function_ret(env, &exp, tail)
+}
+
+// a body that does not produce a number returns zero
+fn build_function_ret(env: &RispEnv, exp: &RispExp, tail: &TailContext) -> Result<(), RispErr> {
+    let value = match exp {
+        RispExp::Number(value) if number_type(env.llvm_context, exp) == tail.ret_type => *value,
+        RispExp::Number(_) => {
+            return Err(RispErr::Reason(format!(
+                "function returns {}, got {}",
+                Type::from_llvm(tail.ret_type),
+                Type::from_llvm(number_type(env.llvm_context, exp))
+            )))
+        }
+        _ => const_number(tail.ret_type, 0.0),
+    };
+    build_ret(&env.llvm_builder, value);
+
+    Ok(())
+}
+
+fn eval_tail_if(
+    env: &mut RispEnv,
+    arg_forms: &[RispExp],
+    tail: &TailContext,
+) -> Result<(), RispErr> {
+    let (test_form, then_form, else_form) = match arg_forms {
+        [test_form, then_form] => (test_form, then_form, &RispExp::Null),
+        [test_form, then_form, else_form] => (test_form, then_form, else_form),
+        _ => return Err(RispErr::Reason("expected (if test then else?)".to_string())),
+    };
+
+    let condition = eval_condition(env, test_form, "if")?;
+
+    // both branches return on their own, so there is no merge block
+    let then_block = append_basic_block_in_context(env.llvm_context, tail.function, "then");
+    let else_block = append_basic_block_in_context(env.llvm_context, tail.function, "else");
+    build_cond_br(&env.llvm_builder, condition, then_block, else_block);
+
+    build_position_at_end(&env.llvm_builder, then_block);
+    eval_tail(env, then_form, tail)?;
+
+    build_position_at_end(&env.llvm_builder, else_block);
+    eval_tail(env, else_form, tail)
+}
+
+fn eval_self_tail_call(
+    env: &mut RispEnv,
+    f_name: &str,
+    arg_forms: &[RispExp],
+    tail: &TailContext,
+) -> Result<(), RispErr> {
+    if arg_forms.len() != tail.slots.len() {
+        return Err(RispErr::Reason(format!(
+            "`{}` expects {} arguments, got {}",
+            f_name,
+            tail.slots.len(),
+            arg_forms.len()
+        )));
+    }
+
+    // every argument is computed before any parameter is overwritten
+    let mut values = vec![];
+    for (index, arg_form) in arg_forms.iter().enumerate() {
+        let arg = eval(arg_form, env)?;
+        let param_type = element_type(type_of(tail.slots[index]));
+        if !matches!(arg, RispExp::Number(_)) || number_type(env.llvm_context, &arg) != param_type {
+            return Err(RispErr::Reason(format!(
+                "`{}` expects {} for argument {}, got {}",
+                f_name,
+                Type::from_llvm(param_type),
+                index + 1,
+                arg
+            )));
+        }
+        values.push(llvm_value(&arg));
+    }
+    for (value, slot) in values.into_iter().zip(tail.slots.iter()) {
+        build_store(&env.llvm_builder, value, *slot);
+    }
+    build_br(&env.llvm_builder, tail.body_block);
+
+    Ok(())
+}
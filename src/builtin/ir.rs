@@ -1,43 +1,124 @@
 use crate::builtin::env::RispEnv;
-use crate::ir::llvm_type::int32_type;
-use crate::ir::operate::{build_alloca, build_int32_value, build_load, build_store, call_function};
+use crate::cli::Target;
+use crate::infer::types::Type;
+use crate::ir::const_value::{const_int, const_real};
+use crate::ir::context::Context;
+use crate::ir::llvm_type::{
+    double_type, function_type, int1_type, int32_type, int64_type, is_double_type, type_of,
+    void_type,
+};
+use crate::ir::operate::{
+    add_function, build_entry_alloca, build_fcmp, build_fptosi, build_icmp, build_int_cast,
+    build_load, build_sitofp, build_uitofp, call_function, get_param,
+};
+use crate::ir::scope::get_named_function;
 use crate::ir::string::codegen_string;
-use crate::{LLVMValueWrapper, RispCallback, RispErr, RispExp};
-use llvm_sys::LLVMValue;
-use std::io;
+use crate::{RispCallback, RispErr, RispExp};
+use llvm_sys::{LLVMBuilder, LLVMIntPredicate, LLVMRealPredicate, LLVMType, LLVMValue};
+use std::convert::TryFrom;
+use std::os::raw::c_char;
 use std::ptr::null_mut;
 
 #[allow(dead_code)]
-pub fn wrap_llvm_value(value: f64, llvm_ref: LLVMValueWrapper) -> RispExp {
-    RispExp::Number(value, llvm_ref)
+pub fn wrap_llvm_value(llvm_ref: *mut LLVMValue) -> RispExp {
+    RispExp::Number(llvm_ref)
 }
 
 #[allow(dead_code)]
 pub fn unwrap_object(exp: &RispExp) -> *mut LLVMValue {
     match *exp {
-        RispExp::Number(_f, (llvm_ref, _)) => llvm_ref,
+        RispExp::Number(llvm_ref) => llvm_ref,
         _ => panic!("failed to unwrap object: {}", exp),
     }
 }
 
-pub fn load_llvm_value(env: &RispEnv, exp: &RispExp) -> *mut LLVMValue {
+pub fn llvm_value(exp: &RispExp) -> *mut LLVMValue {
     match exp {
-        RispExp::Number(_, value_ref) => {
-            if !value_ref.1 {
-                build_load(env.llvm_builder, value_ref.0, "")
-            } else {
-                value_ref.0
-            }
+        RispExp::Number(value) => *value,
+        _ => null_mut(),
+    }
+}
+
+// value of a function body or branch, 0 when it did not produce a number
+pub fn value_or_zero(context: &Context, exp: &RispExp) -> *mut LLVMValue {
+    match exp {
+        RispExp::Number(value) => *value,
+        _ => llvm_integer!(context, 0),
+    }
+}
+
+// type of a number, bool, i32, i64 or double
+pub fn number_type(context: &Context, exp: &RispExp) -> *mut LLVMType {
+    match exp {
+        RispExp::Number(value) => type_of(*value),
+        _ => int32_type(context),
+    }
+}
+
+// integers and doubles are never mixed implicitly, `float` and `int` convert between them
+pub fn operand_type(
+    context: &Context,
+    op: &str,
+    args: &[RispExp],
+) -> Result<*mut LLVMType, RispErr> {
+    let mut types = args.iter().map(|arg| number_type(context, arg));
+    let first = types.next().unwrap_or_else(|| int32_type(context));
+    if first == int1_type(context) {
+        return Err(RispErr::Reason(format!(
+            "`{}` expects numbers, convert booleans with `int`",
+            op
+        )));
+    }
+    if types.any(|llvm_type| llvm_type != first) {
+        return Err(RispErr::Reason(format!(
+            "`{}` cannot mix integer and floating-point operands",
+            op
+        )));
+    }
+    Ok(first)
+}
+
+pub fn const_number(llvm_type: *mut LLVMType, value: f64) -> *mut LLVMValue {
+    if is_double_type(llvm_type) {
+        const_real(llvm_type, value)
+    } else {
+        const_int(llvm_type, value as i64 as u64)
+    }
+}
+
+// converts a loaded bool, i32, i64 or double, booleans count as 0 and 1
+pub fn convert_number(
+    env: &RispEnv,
+    value: *mut LLVMValue,
+    llvm_type: *mut LLVMType,
+    name: &str,
+) -> *mut LLVMValue {
+    let from = type_of(value);
+    let signed = from != int1_type(env.llvm_context);
+    match (is_double_type(from), is_double_type(llvm_type)) {
+        _ if from == llvm_type => value,
+        (true, false) => build_fptosi(&env.llvm_builder, value, llvm_type, name),
+        (false, true) if signed => build_sitofp(&env.llvm_builder, value, llvm_type, name),
+        (false, true) => build_uitofp(&env.llvm_builder, value, llvm_type, name),
+        _ => build_int_cast(&env.llvm_builder, value, llvm_type, signed, name),
+    }
+}
+
+// integer literals are i32 constants, or i64 when they do not fit
+pub fn literal_value(context: &Context, exp: &RispExp) -> *mut LLVMValue {
+    match exp {
+        RispExp::Int(value) if i32::try_from(*value).is_ok() => {
+            llvm_integer!(context, *value as u64)
         }
-        _ => 0 as *mut LLVMValue
+        RispExp::Int(value) => const_int(int64_type(context), *value as u64),
+        RispExp::Float(value) => const_real(double_type(context), *value),
+        _ => null_mut(),
     }
 }
 
-pub fn eval_number(env: &RispEnv, f: f64) -> RispExp {
-    let llvm_input = build_alloca(env.llvm_builder, int32_type(), "");
-    build_store(env.llvm_builder, build_int32_value(f), llvm_input);
-    // let llvm_input = build_load(env.llvm_builder, llvm_input, "");
-    RispExp::Number(f, (llvm_input, false))
+// literals are constants, they never need an instruction of their own
+pub fn eval_number(context: &Context, literal: &RispExp) -> RispExp {
+    RispExp::Number(literal_value(context, literal))
 }
 
 // printf keyword
@@ -51,68 +132,203 @@ pub fn eval_printf_fn(
     let mut llvm_val: *mut LLVMValue = null_mut();
 
     // emit IR
-    if let Some(RispExp::Number(_, llvm_ref)) = first_arg {
-        let llvm_ref = *llvm_ref;
-
-        let printf = env.built_ins["printf"];
-        let llvm_value = build_alloca(env.llvm_builder, int32_type(), "");
-
-        build_store(env.llvm_builder, llvm_ref.0, llvm_value);
-
-        let print_int = build_load(env.llvm_builder, llvm_value, "");
-        let printf_args = vec![codegen_string(env, "Result: %d\n", ""), print_int];
-
-        call_function(env.llvm_builder, printf, printf_args, "");
-        llvm_val = llvm_ref.0;
+    if let Some(arg @ RispExp::Number(llvm_ref)) = first_arg {
+        // booleans are printed as 0 or 1
+        let context = env.llvm_context;
+        let (llvm_type, format, runtime) = match Type::from_llvm(number_type(context, arg)) {
+            Type::Double => (double_type(context), "Result: %f\n", "risp_print_f64"),
+            Type::Long => (int64_type(context), "Result: %ld\n", "risp_print_i64"),
+            Type::Int | Type::Bool => (int32_type(context), "Result: %d\n", "risp_print_i32"),
+        };
+        let print_value = convert_number(env, *llvm_ref, llvm_type, "");
+        match env.target {
+            // the runtime prints the same text as the format would
+            Target::Wasi => {
+                let fn_type = function_type(void_type(context), &mut [llvm_type]);
+                let print = runtime_function(env, runtime, fn_type);
+                call_function(&env.llvm_builder, print, vec![print_value], "");
+            }
+            Target::Host | Target::Wasm32 => {
+                let printf = env.built_ins["printf"];
+                let printf_args = vec![codegen_string(env, format, ""), print_value];
+                call_function(&env.llvm_builder, printf, printf_args, "");
+            }
+        }
+        llvm_val = *llvm_ref;
     }
 
     // eval print
     func(env, &args_eval, Some(llvm_val))
 }
 
-// input keyword
-pub fn eval_input_fn(env: &mut RispEnv, func: &RispCallback) -> Result<RispExp, RispErr> {
-    let input_fn = env.built_ins["input"];
-
-    let llvm_input = build_alloca(env.llvm_builder, int32_type(), "input");
-    let input_args = vec![codegen_string(env, "%u", ""), llvm_input];
-
+// input keyword, `format` is the scanf conversion matching `llvm_type`
+pub fn eval_input_fn(
+    env: &mut RispEnv,
+    func: &RispCallback,
+    llvm_type: *mut LLVMType,
+    format: &str,
+) -> Result<RispExp, RispErr> {
     // emit IR
-    call_function(env.llvm_builder, input_fn, input_args, "");
+    let value = match env.target {
+        Target::Wasi => {
+            let runtime = if is_double_type(llvm_type) {
+                "risp_read_f64"
+            } else {
+                "risp_read_i32"
+            };
+            let read = runtime_function(env, runtime, function_type(llvm_type, &mut []));
+            call_function(&env.llvm_builder, read, vec![], "input")
+        }
+        // scanf needs somewhere to write the value to
+        Target::Host | Target::Wasm32 => {
+            let llvm_input = build_entry_alloca(&env.llvm_builder, llvm_type, "input");
+            let input_fn = env.built_ins["input"];
+            let input_args = vec![codegen_string(env, format, ""), llvm_input];
+            call_function(&env.llvm_builder, input_fn, input_args, "");
+            build_load(&env.llvm_builder, llvm_input, "")
+        }
+    };
 
     // eval
-    func(env, &[], Some(llvm_input))
+    func(env, &[], Some(value))
+}
+
+// declares a function of the WASI runtime, which is linked in after code generation
+fn runtime_function(env: &RispEnv, name: &str, fn_type: *mut LLVMType) -> *mut LLVMValue {
+    get_named_function(&env.llvm_module, name)
+        .unwrap_or_else(|| add_function(&env.llvm_module, fn_type, name))
+}
+
+// user function call
+pub fn eval_call_fn(
+    env: &mut RispEnv,
+    f_name: &str,
+    arity: usize,
+    function: *mut LLVMValue,
+    args_eval: Result<Vec<RispExp>, RispErr>,
+) -> Result<RispExp, RispErr> {
+    let args_eval = args_eval?;
+    if args_eval.len() != arity {
+        return Err(RispErr::Reason(format!(
+            "`{}` expects {} arguments, got {}",
+            f_name,
+            arity,
+            args_eval.len()
+        )));
+    }
+
+    // arguments already have the parameter types inferred for them
+    let call_args = args_eval
+        .iter()
+        .enumerate()
+        .map(|(index, arg)| {
+            let param_type = type_of(get_param(function, index));
+            match arg {
+                RispExp::Number(value) if number_type(env.llvm_context, arg) == param_type => {
+                    Ok(*value)
+                }
+                _ => Err(RispErr::Reason(format!(
+                    "`{}` expects {} for argument {}, got {}",
+                    f_name,
+                    Type::from_llvm(param_type),
+                    index + 1,
+                    arg
+                ))),
+            }
+        })
+        .collect::<Result<Vec<*mut LLVMValue>, RispErr>>()?;
+
+    // emit IR, the result is only known at runtime
+    let llvm_ref = call_function(&env.llvm_builder, function, call_args, "");
+
+    Ok(RispExp::Number(llvm_ref))
 }
 
 // arithmetic
 pub fn eval_arithmetic(
     env: &mut RispEnv,
-    _op: &str,
     func: &RispCallback,
     args_eval: Result<Vec<RispExp>, RispErr>,
 ) -> Result<RispExp, RispErr> {
-    let risp_args = args_eval.unwrap();
+    let risp_args = args_eval?;
     func(env, &risp_args, None)
 }
 
-// utils
-fn parse_single_float(exp: &RispExp) -> Result<f64, RispErr> {
-    match exp {
-        RispExp::Number(num, _) => Ok(*num),
-        _ => Err(RispErr::Reason("expected a number".to_string())),
+pub type BinaryOpBuilder = unsafe extern "C" fn(
+    *mut LLVMBuilder,
+    *mut LLVMValue,
+    *mut LLVMValue,
+    *const c_char,
+) -> *mut LLVMValue;
+
+// chains one instruction per extra operand: (op (op a b) c)...
+pub fn fold_llvm_values(
+    env: &RispEnv,
+    op: &str,
+    args: &[RispExp],
+    (build_int, build_float): (BinaryOpBuilder, BinaryOpBuilder),
+    name: &str,
+) -> Result<*mut LLVMValue, RispErr> {
+    let build = if is_double_type(operand_type(env.llvm_context, op, args)?) {
+        build_float
+    } else {
+        build_int
+    };
+    let mut values = args.iter().map(llvm_value);
+    let first = values.next().unwrap_or_else(null_mut);
+
+    Ok(values.fold(first, |lhs, rhs| unsafe {
+        build(
+            env.llvm_builder.as_raw(),
+            lhs,
+            rhs,
+            c_string!(name).as_ptr(),
+        )
+    }))
+}
+
+// LLVM value of a one-argument builtin
+pub fn single_argument(f_name: &str, args: &[RispExp]) -> Result<*mut LLVMValue, RispErr> {
+    expect_numbers(args)?;
+    match args {
+        [arg] => Ok(llvm_value(arg)),
+        _ => Err(RispErr::Reason(format!(
+            "`{}` expects 1 argument, got {}",
+            f_name,
+            args.len()
+        ))),
     }
 }
 
-pub fn get_input(prompt: &str) -> String {
-    println!("{}", prompt);
-    let mut input = String::new();
-    match io::stdin().read_line(&mut input) {
-        Ok(_) => {}
-        Err(_) => {}
+// comparison, the result is an i1 that arithmetic has to widen with `int`
+pub fn compare(
+    env: &RispEnv,
+    args: &[RispExp],
+    (predicate, real_predicate): (LLVMIntPredicate, LLVMRealPredicate),
+) -> Result<RispExp, RispErr> {
+    expect_numbers(args)?;
+    if args.len() != 2 {
+        return Err(RispErr::Reason(format!(
+            "comparison expects 2 arguments, got {}",
+            args.len()
+        )));
     }
-    input.trim().to_string()
+
+    // IR
+    let is_double = is_double_type(operand_type(env.llvm_context, "comparison", args)?);
+    let (arg1, arg2) = (llvm_value(&args[0]), llvm_value(&args[1]));
+    let cmp = if is_double {
+        build_fcmp(&env.llvm_builder, real_predicate, arg1, arg2, "cmp")
+    } else {
+        build_icmp(&env.llvm_builder, predicate, arg1, arg2, "cmp")
+    };
+    Ok(RispExp::Number(cmp))
 }
 
-pub fn parse_list_of_floats(args: &[RispExp]) -> Result<Vec<f64>, RispErr> {
-    args.iter().map(|x| parse_single_float(x)).collect()
+// utils
+pub fn expect_numbers(args: &[RispExp]) -> Result<(), RispErr> {
+    match args.iter().find(|arg| !matches!(arg, RispExp::Number(_))) {
+        Some(arg) => Err(RispErr::Reason(format!("expected a number, got {}", arg))),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,223 @@
+use crate::optimize::pipeline::LEVELS;
+use crate::RispErr;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::fs;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Emit {
+    LlvmIr,
+    Bitcode,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl Emit {
+    pub const VALUES: &'static [&'static str] = &["llvm-ir", "bc", "asm", "obj", "exe"];
+
+    pub fn default_output(self, target: Target) -> &'static str {
+        match (self, target) {
+            (Emit::LlvmIr, _) => "output.ll",
+            (Emit::Bitcode, _) => "output.bc",
+            (Emit::Asm, _) => "output.s",
+            (Emit::Obj, _) => "output.o",
+            (Emit::Exe, Target::Host) => "output",
+            (Emit::Exe, Target::Wasm32) | (Emit::Exe, Target::Wasi) => "output.wasm",
+        }
+    }
+}
+
+impl FromStr for Emit {
+    type Err = RispErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "llvm-ir" => Ok(Emit::LlvmIr),
+            "bc" => Ok(Emit::Bitcode),
+            "asm" => Ok(Emit::Asm),
+            "obj" => Ok(Emit::Obj),
+            "exe" => Ok(Emit::Exe),
+            _ => Err(RispErr::Reason(format!("unknown emit kind `{}`", s))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Host,
+    Wasm32,
+    // printing and input go through WASI instead of imported libc functions
+    Wasi,
+}
+
+impl Target {
+    pub const VALUES: &'static [&'static str] = &["host", "wasm32-unknown-unknown", "wasm32-wasi"];
+}
+
+impl FromStr for Target {
+    type Err = RispErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "host" => Ok(Target::Host),
+            "wasm32-unknown-unknown" => Ok(Target::Wasm32),
+            "wasm32-wasi" => Ok(Target::Wasi),
+            _ => Err(RispErr::Reason(format!("unknown target `{}`", s))),
+        }
+    }
+}
+
+pub struct Options {
+    pub input: Option<String>,
+    pub output: String,
+    pub emit: Emit,
+    pub target: Target,
+    // bitcode modules linked into the program before it is emitted
+    pub link: Vec<String>,
+    pub opt_level: u32,
+    pub fold: bool,
+    pub verify: bool,
+}
+
+pub enum Command {
+    Compile(Options),
+    // runs the program with the tree-walking interpreter instead of compiling it
+    Interpret(Option<String>),
+    // compiles the program in memory and runs it, exits with its result
+    Run(Option<String>),
+    // compiles and runs each entry read from stdin as soon as it is complete
+    Repl,
+}
+
+fn input_arg() -> Arg<'static, 'static> {
+    Arg::with_name("INPUT")
+        .help("Source file to compile, reads stdin when omitted or `-`")
+        .index(1)
+}
+
+pub fn build_cli() -> App<'static, 'static> {
+    App::new("llvm-rust")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Compiles a simple Lisp-like language to LLVM")
+        .arg(input_arg())
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Write the result to FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("emit")
+                .long("emit")
+                .value_name("KIND")
+                .help("Kind of output to produce")
+                .possible_values(Emit::VALUES)
+                .default_value("llvm-ir"),
+        )
+        .arg(
+            Arg::with_name("target")
+                .long("target")
+                .value_name("TRIPLE")
+                .help("Machine to generate code for")
+                .possible_values(Target::VALUES)
+                .default_value("host"),
+        )
+        .arg(
+            Arg::with_name("opt-level")
+                .short("O")
+                .value_name("LEVEL")
+                .help("Optimize with LLVM's pipeline for LEVEL and report the instruction count")
+                .possible_values(LEVELS)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("no-fold")
+                .long("no-fold")
+                .help("Compile constant expressions as they are instead of folding them"),
+        )
+        .arg(
+            Arg::with_name("no-verify")
+                .long("no-verify")
+                .help("Emit the generated module without running the LLVM verifier on it"),
+        )
+        .arg(
+            Arg::with_name("link")
+                .long("link")
+                .value_name("FILE")
+                .help("Link the LLVM bitcode in FILE into the program, may be repeated")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .subcommand(
+            SubCommand::with_name("interpret")
+                .about("Runs a program without compiling it, exits with its result")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Compiles a program with the JIT and runs it, exits with its result")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("repl")
+                .about("Reads expressions from stdin, runs each one and prints its value"),
+        )
+}
+
+pub fn parse_command(matches: &ArgMatches) -> Result<Command, RispErr> {
+    match matches.subcommand() {
+        ("interpret", Some(sub_matches)) => Ok(Command::Interpret(
+            sub_matches.value_of("INPUT").map(|x| x.to_string()),
+        )),
+        ("run", Some(sub_matches)) => Ok(Command::Run(
+            sub_matches.value_of("INPUT").map(|x| x.to_string()),
+        )),
+        ("repl", Some(_)) => Ok(Command::Repl),
+        _ => parse_options(matches).map(Command::Compile),
+    }
+}
+
+pub fn parse_options(matches: &ArgMatches) -> Result<Options, RispErr> {
+    let emit: Emit = matches.value_of("emit").unwrap_or("llvm-ir").parse()?;
+    let target: Target = matches.value_of("target").unwrap_or("host").parse()?;
+    let output = matches
+        .value_of("output")
+        .unwrap_or_else(|| emit.default_output(target))
+        .to_string();
+
+    Ok(Options {
+        input: matches.value_of("INPUT").map(|x| x.to_string()),
+        output,
+        emit,
+        target,
+        link: matches
+            .values_of("link")
+            .map_or(vec![], |paths| paths.map(|x| x.to_string()).collect()),
+        opt_level: matches
+            .value_of("opt-level")
+            .unwrap_or("0")
+            .parse()
+            .unwrap_or(0),
+        fold: !matches.is_present("no-fold"),
+        verify: !matches.is_present("no-verify"),
+    })
+}
+
+pub fn read_source(input: Option<&str>) -> Result<String, RispErr> {
+    match input {
+        Some(path) if path != "-" => fs::read_to_string(path)
+            .map_err(|e| RispErr::Reason(format!("cannot read `{}`: {}", path, e))),
+        _ => {
+            let mut source = String::new();
+            io::stdin()
+                .read_to_string(&mut source)
+                .map_err(|e| RispErr::Reason(format!("cannot read stdin: {}", e)))?;
+            Ok(source)
+        }
+    }
+}
@@ -0,0 +1,610 @@
+use crate::builtin::form::cond_to_if;
+use crate::infer::types::Type;
+use crate::{RispErr, RispExp};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+// what an expression evaluates to
+#[derive(Clone, Copy)]
+enum Typed {
+    Value(Type),
+    Function(usize),
+    Nothing,
+}
+
+#[derive(Clone, Copy)]
+enum Binding {
+    Var(Type),
+    Function(usize),
+}
+
+// `None` until a call site or the body says otherwise, then read as i32
+#[derive(Clone, PartialEq)]
+struct Signature {
+    params: Vec<Option<Type>>,
+    ret: Option<Type>,
+}
+
+impl Signature {
+    fn new(arity: usize) -> Self {
+        Signature {
+            params: vec![None; arity],
+            ret: None,
+        }
+    }
+
+    fn param(&self, index: usize) -> Type {
+        self.params[index].unwrap_or(Type::Int)
+    }
+
+    fn ret(&self) -> Type {
+        self.ret.unwrap_or(Type::Int)
+    }
+
+    // `None` orders before any type, so `max` keeps the wider one
+    fn join(&self, other: &Signature) -> Signature {
+        Signature {
+            params: self
+                .params
+                .iter()
+                .zip(other.params.iter())
+                .map(|(a, b)| *a.max(b))
+                .collect(),
+            ret: self.ret.max(other.ret),
+        }
+    }
+}
+
+struct Inference {
+    // globals first, innermost last
+    scopes: Vec<HashMap<String, Binding>>,
+    // a global variable keeps its type even when the name is rebound
+    globals: HashMap<String, Type>,
+    // indexed by the order in which definitions are met
+    assumed: Vec<Signature>,
+    observed: Vec<Signature>,
+    // functions before this index are already compiled and cannot widen
+    fixed: usize,
+}
+
+// the global scope left behind by earlier entries of a session
+#[derive(Clone, Default)]
+pub struct Globals {
+    scope: HashMap<String, Binding>,
+    types: HashMap<String, Type>,
+    signatures: Vec<Signature>,
+}
+
+// assigns a type to every expression, makes conversions explicit with `float`, `int`
+// and `long`, and annotates functions as (defn name ((param type)...) -> type body...)
+pub fn infer_program(forms: &[RispExp]) -> Result<Vec<RispExp>, RispErr> {
+    infer_entry(forms, &Globals::default()).map(|(typed, _)| typed)
+}
+
+// infers `forms` on top of earlier definitions, which keep the types they were
+// compiled with, and returns the globals extended with the new definitions
+pub fn infer_entry(
+    forms: &[RispExp],
+    globals: &Globals,
+) -> Result<(Vec<RispExp>, Globals), RispErr> {
+    let mut assumed = globals.signatures.clone();
+    loop {
+        let mut inference = Inference {
+            scopes: vec![globals.scope.clone()],
+            globals: globals.types.clone(),
+            assumed: assumed.clone(),
+            observed: globals.signatures.clone(),
+            fixed: globals.signatures.len(),
+        };
+        let typed = forms
+            .iter()
+            .map(|form| inference.infer_statement(form).map(|(exp, _)| exp))
+            .collect::<Result<Vec<RispExp>, RispErr>>()?;
+
+        // types only ever widen, so this settles after a few rounds
+        let widened = inference
+            .assumed
+            .iter()
+            .zip(inference.observed.iter())
+            .map(|(a, b)| a.join(b))
+            .collect::<Vec<Signature>>();
+        if widened == inference.assumed {
+            let globals = Globals {
+                scope: inference.scopes.swap_remove(0),
+                types: inference.globals,
+                signatures: inference.assumed,
+            };
+            return Ok((typed, globals));
+        }
+        assumed = widened;
+    }
+}
+
+fn symbol(name: &str) -> RispExp {
+    RispExp::Symbol(name.to_string())
+}
+
+// wraps `exp` in the builtin that widens it, narrowing is never implicit
+fn coerce(exp: RispExp, from: Type, to: Type) -> RispExp {
+    if from >= to {
+        return exp;
+    }
+    match to.conversion() {
+        Some(name) => RispExp::List(vec![symbol(name), exp]),
+        None => exp,
+    }
+}
+
+fn expect_value(typed: Typed, form: &RispExp) -> Result<Type, RispErr> {
+    match typed {
+        Typed::Value(t) => Ok(t),
+        Typed::Function(_) => Err(RispErr::Reason(format!(
+            "expected a number, got function {}",
+            form
+        ))),
+        Typed::Nothing => Err(RispErr::Reason(format!("expected a number, got {}", form))),
+    }
+}
+
+impl Inference {
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+    }
+
+    fn bind(&mut self, name: &str, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), binding);
+        }
+    }
+
+    fn is_builtin(&self, name: &str) -> bool {
+        self.lookup(name).is_none() && builtin_type(name, &[]).is_some()
+    }
+
+    fn infer(&mut self, exp: &RispExp) -> Result<(RispExp, Typed), RispErr> {
+        match exp {
+            RispExp::Int(value) if i32::try_from(*value).is_ok() => {
+                Ok((exp.clone(), Typed::Value(Type::Int)))
+            }
+            RispExp::Int(_) => Ok((exp.clone(), Typed::Value(Type::Long))),
+            RispExp::Float(_) => Ok((exp.clone(), Typed::Value(Type::Double))),
+            RispExp::Symbol(name) => match self.lookup(name) {
+                Some(Binding::Var(t)) => Ok((exp.clone(), Typed::Value(t))),
+                Some(Binding::Function(id)) => Ok((exp.clone(), Typed::Function(id))),
+                None if self.is_builtin(name) => Err(RispErr::Reason(format!(
+                    "builtin `{}` can only be called",
+                    name
+                ))),
+                None => Err(RispErr::Reason(format!("unexpected symbol k='{}'", name))),
+            },
+            RispExp::List(list) => self.infer_list(exp, list),
+            RispExp::Null => Ok((RispExp::Null, Typed::Nothing)),
+            RispExp::Number(_)
+            | RispExp::Variable(_)
+            | RispExp::Func(_, _)
+            | RispExp::Lambda(_, _, _) => Err(RispErr::Reason("unexpected form".to_string())),
+        }
+    }
+
+    // a form at top level or in a function body, the only places a `define`
+    // may appear, so its binding never depends on a branch being taken
+    fn infer_statement(&mut self, exp: &RispExp) -> Result<(RispExp, Typed), RispErr> {
+        match exp {
+            RispExp::List(list) => match &list[..] {
+                [RispExp::Symbol(name), arg_forms @ ..] if name == "define" => {
+                    self.infer_define(exp, arg_forms)
+                }
+                _ => self.infer(exp),
+            },
+            _ => self.infer(exp),
+        }
+    }
+
+    fn infer_list(&mut self, exp: &RispExp, list: &[RispExp]) -> Result<(RispExp, Typed), RispErr> {
+        let (first_form, arg_forms) = list
+            .split_first()
+            .ok_or(RispErr::Reason("expected a non-empty list".to_string()))?;
+
+        if let RispExp::Symbol(name) = first_form {
+            match &name[..] {
+                "define" => {
+                    return Err(RispErr::Reason(format!(
+                        "`define` is only allowed at top level or in a function body, got {}",
+                        exp
+                    )))
+                }
+                "set!" => return self.infer_set(exp, arg_forms),
+                "if" => return self.infer_if(arg_forms),
+                "cond" => return self.infer(&cond_to_if(arg_forms)?),
+                "while" => return self.infer_while(arg_forms),
+                "defn" => {
+                    return match arg_forms {
+                        [RispExp::Symbol(name), RispExp::List(params), body @ ..]
+                            if !body.is_empty() =>
+                        {
+                            self.check_not_builtin(name)?;
+                            self.infer_function(Some(name), params, body)
+                        }
+                        _ => Err(RispErr::Reason(
+                            "expected (defn name (params...) body...)".to_string(),
+                        )),
+                    }
+                }
+                "lambda" => {
+                    return match arg_forms {
+                        [RispExp::List(params), body @ ..] if !body.is_empty() => {
+                            self.infer_function(None, params, body)
+                        }
+                        _ => Err(RispErr::Reason(
+                            "expected (lambda (params...) body...)".to_string(),
+                        )),
+                    }
+                }
+                _ if self.is_builtin(name) => return self.infer_builtin(name, arg_forms),
+                _ => {}
+            }
+        }
+
+        let (callee, typed) = self.infer(first_form)?;
+        match typed {
+            Typed::Function(id) => self.infer_call(callee, id, arg_forms),
+            _ => Err(RispErr::Reason("first form must be a function".to_string())),
+        }
+    }
+
+    fn check_not_builtin(&self, name: &str) -> Result<(), RispErr> {
+        if self.is_builtin(name) {
+            return Err(RispErr::Reason(format!(
+                "cannot redefine builtin `{}`",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    fn infer_args(&mut self, arg_forms: &[RispExp]) -> Result<Vec<(RispExp, Type)>, RispErr> {
+        arg_forms
+            .iter()
+            .map(|arg_form| {
+                let (arg, typed) = self.infer(arg_form)?;
+                Ok((arg, expect_value(typed, arg_form)?))
+            })
+            .collect()
+    }
+
+    fn infer_builtin(
+        &mut self,
+        name: &str,
+        arg_forms: &[RispExp],
+    ) -> Result<(RispExp, Typed), RispErr> {
+        let args = self.infer_args(arg_forms)?;
+        let types = args.iter().map(|(_, t)| *t).collect::<Vec<Type>>();
+        let (operand, typed) = builtin_type(name, &types).unwrap_or((None, Typed::Nothing));
+
+        let mut form = vec![symbol(name)];
+        form.extend(args.into_iter().map(|(arg, t)| match operand {
+            Some(operand) => coerce(arg, t, operand),
+            None => arg,
+        }));
+
+        Ok((RispExp::List(form), typed))
+    }
+
+    // (define name expr)
+    fn infer_define(
+        &mut self,
+        exp: &RispExp,
+        arg_forms: &[RispExp],
+    ) -> Result<(RispExp, Typed), RispErr> {
+        let (name, value_form) = match arg_forms {
+            [RispExp::Symbol(name), value_form] => (name, value_form),
+            _ => return Err(RispErr::Reason("expected (define name expr)".to_string())),
+        };
+        self.check_not_builtin(name)?;
+
+        let (value, typed) = self.infer(value_form)?;
+        let (value, typed) = match typed {
+            Typed::Function(id) => {
+                self.bind(name, Binding::Function(id));
+                (value, typed)
+            }
+            Typed::Nothing => {
+                return Err(RispErr::Reason(format!(
+                    "`{}` must be bound to a number",
+                    name
+                )))
+            }
+            Typed::Value(t) => {
+                let is_global = self.scopes.len() == 1;
+                let defined = match self.globals.get(name) {
+                    Some(defined) if is_global && t > *defined => {
+                        return Err(RispErr::Reason(format!(
+                            "`{}` was defined as {}, got {} in {}",
+                            name, defined, t, exp
+                        )))
+                    }
+                    Some(defined) if is_global => *defined,
+                    _ => t,
+                };
+                if is_global {
+                    self.globals.insert(name.to_string(), defined);
+                }
+                self.bind(name, Binding::Var(defined));
+                (coerce(value, t, defined), Typed::Value(defined))
+            }
+        };
+
+        Ok((
+            RispExp::List(vec![symbol("define"), symbol(name), value]),
+            typed,
+        ))
+    }
+
+    // (set! name expr)
+    fn infer_set(
+        &mut self,
+        exp: &RispExp,
+        arg_forms: &[RispExp],
+    ) -> Result<(RispExp, Typed), RispErr> {
+        let (name, value_form) = match arg_forms {
+            [RispExp::Symbol(name), value_form] => (name, value_form),
+            _ => return Err(RispErr::Reason("expected (set! name expr)".to_string())),
+        };
+
+        let defined = match self.lookup(name) {
+            Some(Binding::Var(t)) => t,
+            Some(_) => return Err(RispErr::Reason(format!("cannot assign to `{}`", name))),
+            None if self.is_builtin(name) => {
+                return Err(RispErr::Reason(format!("cannot assign to `{}`", name)))
+            }
+            None => return Err(RispErr::Reason(format!("unexpected symbol k='{}'", name))),
+        };
+
+        let (value, typed) = self.infer(value_form)?;
+        let t = match typed {
+            Typed::Value(t) => t,
+            _ => {
+                return Err(RispErr::Reason(format!(
+                    "`{}` must be assigned a number",
+                    name
+                )))
+            }
+        };
+        if t > defined {
+            return Err(RispErr::Reason(format!(
+                "`{}` is {}, cannot assign {} in {}",
+                name, defined, t, exp
+            )));
+        }
+
+        Ok((
+            RispExp::List(vec![
+                symbol("set!"),
+                symbol(name),
+                coerce(value, t, defined),
+            ]),
+            Typed::Value(defined),
+        ))
+    }
+
+    // (if test then else?), both branches are widened to the wider of the two
+    fn infer_if(&mut self, arg_forms: &[RispExp]) -> Result<(RispExp, Typed), RispErr> {
+        let (test_form, branch_forms) = match arg_forms {
+            [test_form, branch_forms @ ..] if matches!(branch_forms.len(), 1 | 2) => {
+                (test_form, branch_forms)
+            }
+            _ => return Err(RispErr::Reason("expected (if test then else?)".to_string())),
+        };
+
+        let (test, typed) = self.infer(test_form)?;
+        expect_value(typed, test_form)?;
+
+        let mut branches = vec![];
+        for branch_form in branch_forms.iter() {
+            let (branch, typed) = self.infer(branch_form)?;
+            let t = match typed {
+                Typed::Function(_) => {
+                    return Err(RispErr::Reason(format!(
+                        "`if` branches must be numbers, got function {}",
+                        branch_form
+                    )))
+                }
+                Typed::Value(t) => Some(t),
+                Typed::Nothing => None,
+            };
+            branches.push((branch, t));
+        }
+
+        // a branch without a number yields zero, an `if` without any yields an i32
+        let t = branches
+            .iter()
+            .filter_map(|(_, t)| *t)
+            .max()
+            .unwrap_or(Type::Int);
+
+        // the zero of a missing else is spelled out so the interpreter knows its type,
+        // booleans have no literal and are left to the `if`
+        if branches.len() == 1 && t != Type::Bool {
+            branches.push((RispExp::Null, None));
+        }
+        let mut form = vec![symbol("if"), test];
+        form.extend(
+            branches
+                .into_iter()
+                .map(|(branch, from)| match (branch, from) {
+                    (RispExp::Null, _) if t != Type::Bool => coerce(RispExp::Int(0), Type::Int, t),
+                    (branch, Some(from)) => coerce(branch, from, t),
+                    (branch, None) => branch,
+                }),
+        );
+
+        Ok((RispExp::List(form), Typed::Value(t)))
+    }
+
+    // (while test body...)
+    fn infer_while(&mut self, arg_forms: &[RispExp]) -> Result<(RispExp, Typed), RispErr> {
+        let (test_form, body) = arg_forms
+            .split_first()
+            .ok_or(RispErr::Reason("expected (while test body...)".to_string()))?;
+
+        let (test, typed) = self.infer(test_form)?;
+        expect_value(typed, test_form)?;
+
+        let mut form = vec![symbol("while"), test];
+        for body_form in body.iter() {
+            form.push(self.infer(body_form)?.0);
+        }
+
+        Ok((RispExp::List(form), Typed::Nothing))
+    }
+
+    fn infer_function(
+        &mut self,
+        name: Option<&String>,
+        param_forms: &[RispExp],
+        body: &[RispExp],
+    ) -> Result<(RispExp, Typed), RispErr> {
+        let params = param_forms
+            .iter()
+            .map(|param| match param {
+                RispExp::Symbol(s) => Ok(s.clone()),
+                _ => Err(RispErr::Reason(format!("invalid parameter {}", param))),
+            })
+            .collect::<Result<Vec<String>, RispErr>>()?;
+
+        let id = self.observed.len();
+        if self.assumed.len() <= id {
+            self.assumed.push(Signature::new(params.len()));
+        }
+        self.observed.push(Signature::new(params.len()));
+        let signature = self.assumed[id].clone();
+
+        // mirrors the scoping of `compile_function`
+        if let Some(name) = name {
+            self.bind(name, Binding::Function(id));
+        }
+        let outer_scopes = self.scopes.split_off(1);
+        self.scopes.push(HashMap::new());
+        if let Some(name) = name {
+            self.bind(name, Binding::Function(id));
+        }
+        for (index, param) in params.iter().enumerate() {
+            self.bind(param, Binding::Var(signature.param(index)));
+        }
+
+        let result = self.infer_body(body, signature.ret());
+        self.scopes.truncate(1);
+        self.scopes.extend(outer_scopes);
+        let (body, ret) = result?;
+        self.observed[id].ret = Some(ret);
+
+        let typed_params = params
+            .iter()
+            .enumerate()
+            .map(|(index, param)| {
+                RispExp::List(vec![
+                    symbol(param),
+                    symbol(&signature.param(index).to_string()),
+                ])
+            })
+            .collect::<Vec<RispExp>>();
+
+        let mut form = match name {
+            Some(name) => vec![symbol("defn"), symbol(name)],
+            None => vec![symbol("lambda")],
+        };
+        form.push(RispExp::List(typed_params));
+        form.push(symbol("->"));
+        form.push(symbol(&signature.ret().to_string()));
+        form.extend(body);
+
+        Ok((RispExp::List(form), Typed::Function(id)))
+    }
+
+    // the body with its last form widened to `ret`, and the type that form has
+    fn infer_body(&mut self, body: &[RispExp], ret: Type) -> Result<(Vec<RispExp>, Type), RispErr> {
+        let mut forms = vec![];
+        let mut last = Typed::Nothing;
+        for form in body.iter() {
+            let (exp, typed) = self.infer_statement(form)?;
+            forms.push(exp);
+            last = typed;
+        }
+
+        let t = match last {
+            Typed::Value(t) => t,
+            // a body without a number returns zero
+            Typed::Nothing => return Ok((forms, Type::Int)),
+            Typed::Function(_) => {
+                return Err(RispErr::Reason(format!(
+                    "functions must return a number, got {}",
+                    body[body.len() - 1]
+                )))
+            }
+        };
+        if let Some(exp) = forms.pop() {
+            forms.push(coerce(exp, t, ret));
+        }
+
+        Ok((forms, t))
+    }
+
+    fn infer_call(
+        &mut self,
+        callee: RispExp,
+        id: usize,
+        arg_forms: &[RispExp],
+    ) -> Result<(RispExp, Typed), RispErr> {
+        let signature = self.assumed[id].clone();
+        if arg_forms.len() != signature.params.len() {
+            return Err(RispErr::Reason(format!(
+                "`{}` expects {} arguments, got {}",
+                callee,
+                signature.params.len(),
+                arg_forms.len()
+            )));
+        }
+
+        let mut form = vec![callee];
+        for (index, (arg, t)) in self.infer_args(arg_forms)?.into_iter().enumerate() {
+            if id < self.fixed && t > signature.param(index) {
+                return Err(RispErr::Reason(format!(
+                    "`{}` takes {} for argument {}, got {}",
+                    form[0],
+                    signature.param(index),
+                    index + 1,
+                    t
+                )));
+            }
+            let observed = &mut self.observed[id].params[index];
+            *observed = (*observed).max(Some(t));
+            form.push(coerce(arg, t, signature.param(index)));
+        }
+
+        Ok((RispExp::List(form), Typed::Value(signature.ret())))
+    }
+}
+
+// the type operands are widened to and the result type of a builtin call
+fn builtin_type(name: &str, types: &[Type]) -> Option<(Option<Type>, Typed)> {
+    // booleans take part in arithmetic as 0 and 1
+    let operand = types.iter().fold(Type::Int, |a, b| a.join(*b));
+    match name {
+        "+" | "-" | "*" | "/" => Some((Some(operand), Typed::Value(operand))),
+        "=" | "<" | ">" | "<=" | ">=" => Some((Some(operand), Typed::Value(Type::Bool))),
+        "float" => Some((None, Typed::Value(Type::Double))),
+        "int" => Some((None, Typed::Value(Type::Int))),
+        "long" => Some((None, Typed::Value(Type::Long))),
+        "printf" => Some((
+            None,
+            types.first().map_or(Typed::Nothing, |t| Typed::Value(*t)),
+        )),
+        "input" => Some((None, Typed::Value(Type::Int))),
+        "input-float" => Some((None, Typed::Value(Type::Double))),
+        _ => None,
+    }
+}
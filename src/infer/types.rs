@@ -0,0 +1,74 @@
+use crate::ir::context::Context;
+use crate::ir::llvm_type::{
+    double_type, int1_type, int32_type, int64_type, int_width, is_double_type,
+};
+use llvm_sys::LLVMType;
+use std::fmt;
+
+// ordered so that the wider of two types is the larger one
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Type {
+    Bool,
+    Int,
+    Long,
+    Double,
+}
+
+impl Type {
+    pub fn join(self, other: Type) -> Type {
+        self.max(other)
+    }
+
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "bool" => Some(Type::Bool),
+            "i32" => Some(Type::Int),
+            "i64" => Some(Type::Long),
+            "double" => Some(Type::Double),
+            _ => None,
+        }
+    }
+
+    pub fn from_llvm(llvm_type: *mut LLVMType) -> Type {
+        if is_double_type(llvm_type) {
+            return Type::Double;
+        }
+        match int_width(llvm_type) {
+            64 => Type::Long,
+            1 => Type::Bool,
+            _ => Type::Int,
+        }
+    }
+
+    pub fn llvm_type(self, context: &Context) -> *mut LLVMType {
+        match self {
+            Type::Bool => int1_type(context),
+            Type::Int => int32_type(context),
+            Type::Long => int64_type(context),
+            Type::Double => double_type(context),
+        }
+    }
+
+    // builtin that converts a value to this type, booleans are only produced by comparisons
+    pub fn conversion(self) -> Option<&'static str> {
+        match self {
+            Type::Bool => None,
+            Type::Int => Some("int"),
+            Type::Long => Some("long"),
+            Type::Double => Some("float"),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::Bool => "bool",
+            Type::Int => "i32",
+            Type::Long => "i64",
+            Type::Double => "double",
+        };
+
+        write!(f, "{}", name)
+    }
+}
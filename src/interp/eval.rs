@@ -0,0 +1,450 @@
+use crate::builtin::form::cond_to_if;
+use crate::infer::pass::infer_program;
+use crate::infer::types::Type;
+use crate::interp::value::{arithmetic, compare, FloatOp, IntOp, Value};
+use crate::{parse_program, tokenize, RispErr, RispExp};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::thread;
+
+// calls that are not tail calls nest on the Rust stack, the interpreter runs on a
+// thread whose stack holds this many of them
+const MAX_DEPTH: usize = 10_000;
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// walks the inferred program directly, nothing here goes through LLVM
+pub struct Interpreter {
+    // globals first, innermost last
+    scopes: Vec<HashMap<String, Value>>,
+    // words read from stdin that `input` has not consumed yet
+    input: VecDeque<String>,
+    functions: Vec<Rc<Function>>,
+    // every defn or lambda form becomes one function, however often it runs
+    defined: HashMap<*const RispExp, usize>,
+    // calls in progress, tail calls reuse the caller's
+    depth: usize,
+}
+
+// a user function with the signature type inference gave it
+struct Function {
+    name: Option<String>,
+    params: Vec<String>,
+    ret: Type,
+    body: Vec<RispExp>,
+}
+
+// a form in tail position leaves calls to the caller, so they take no stack
+enum Tail {
+    Value(Value),
+    Call(usize, Vec<Value>),
+}
+
+// runs a program and returns the exit code its compiled `main` would
+pub fn interpret(program: &str) -> Result<i32, RispErr> {
+    let program = program.to_string();
+    let handle = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || run_program(&program))
+        .map_err(|e| RispErr::Reason(format!("cannot start the interpreter: {}", e)))?;
+    handle
+        .join()
+        .unwrap_or_else(|_| Err(RispErr::Reason("the interpreter panicked".to_string())))
+}
+
+fn run_program(program: &str) -> Result<i32, RispErr> {
+    let forms = infer_program(&parse_program(&tokenize(program))?)?;
+
+    let mut interpreter = Interpreter::new();
+    let mut value = Value::Null;
+    for form in forms.iter() {
+        value = interpreter.eval(form)?;
+    }
+    io::stdout().flush().ok();
+
+    match value {
+        Value::Null => Ok(0),
+        value => match value.convert(Type::Int)? {
+            Value::Int(code) => Ok(code),
+            _ => Ok(0),
+        },
+    }
+}
+
+const ADD: (IntOp, FloatOp) = (|a, b| Some(a.wrapping_add(b)), |a, b| a + b);
+const SUB: (IntOp, FloatOp) = (|a, b| Some(a.wrapping_sub(b)), |a, b| a - b);
+const MUL: (IntOp, FloatOp) = (|a, b| Some(a.wrapping_mul(b)), |a, b| a * b);
+const DIV: (IntOp, FloatOp) = (
+    |a, b| {
+        if b == 0 {
+            None
+        } else {
+            Some(a.wrapping_div(b))
+        }
+    },
+    |a, b| a / b,
+);
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            scopes: vec![HashMap::new()],
+            input: VecDeque::new(),
+            functions: vec![],
+            defined: HashMap::new(),
+            depth: 0,
+        }
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+
+    fn bind(&mut self, name: &str, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), value);
+        }
+    }
+
+    pub fn eval(&mut self, exp: &RispExp) -> Result<Value, RispErr> {
+        match exp {
+            RispExp::Int(value) => Ok(match i32::try_from(*value) {
+                Ok(value) => Value::Int(value),
+                Err(_) => Value::Long(*value),
+            }),
+            RispExp::Float(value) => Ok(Value::Double(*value)),
+            RispExp::Symbol(k) => self
+                .lookup_mut(k)
+                .map(|value| *value)
+                .ok_or_else(|| RispErr::Reason(format!("unexpected symbol k='{}'", k))),
+            RispExp::List(list) => self.eval_list(list),
+            RispExp::Null => Ok(Value::Null),
+            RispExp::Number(_)
+            | RispExp::Variable(_)
+            | RispExp::Func(_, _)
+            | RispExp::Lambda(_, _, _) => Err(RispErr::Reason("unexpected form".to_string())),
+        }
+    }
+
+    fn eval_list(&mut self, list: &[RispExp]) -> Result<Value, RispErr> {
+        let (first_form, arg_forms) = list
+            .split_first()
+            .ok_or_else(|| RispErr::Reason("expected a non-empty list".to_string()))?;
+
+        if let RispExp::Symbol(name) = first_form {
+            if let Some(result) = self.eval_special_form(list, name, arg_forms) {
+                return result;
+            }
+        }
+
+        let args = arg_forms
+            .iter()
+            .map(|x| self.eval(x))
+            .collect::<Result<Vec<Value>, RispErr>>()?;
+        match (self.callee(first_form)?, first_form) {
+            (Some(id), _) => self.call(id, args),
+            (None, RispExp::Symbol(name)) => self.eval_builtin(name, &args),
+            (None, _) => Err(RispErr::Reason("first form must be a function".to_string())),
+        }
+    }
+
+    fn eval_special_form(
+        &mut self,
+        list: &[RispExp],
+        name: &str,
+        arg_forms: &[RispExp],
+    ) -> Option<Result<Value, RispErr>> {
+        let result = match (name, arg_forms) {
+            ("define", [RispExp::Symbol(name), value_form]) => self
+                .eval(value_form)
+                .inspect(|value| self.bind(name, *value)),
+            ("set!", [RispExp::Symbol(name), value_form]) => {
+                self.eval(value_form).and_then(|value| {
+                    let slot = self.lookup_mut(name).ok_or_else(|| {
+                        RispErr::Reason(format!("unexpected symbol k='{}'", name))
+                    })?;
+                    *slot = value;
+                    Ok(value)
+                })
+            }
+            // inference spells out the zero of a missing else, a branch without a
+            // number yields an i32 zero like the compiled `if`
+            ("if", [test_form, branches @ ..]) => self
+                .pick_branch(test_form, branches)
+                .and_then(|branch| match branch {
+                    Some(branch) => self.eval(branch),
+                    None => Ok(Value::Null),
+                })
+                .map(|value| match value {
+                    Value::Null => Value::Int(0),
+                    value => value,
+                }),
+            ("cond", clauses) => cond_to_if(clauses).and_then(|exp| self.eval(&exp)),
+            ("while", [test_form, body @ ..]) => self.eval_while(test_form, body),
+            ("defn", [RispExp::Symbol(f_name), RispExp::List(params), body @ ..]) => self
+                .define_function(list, Some(f_name), params, body)
+                .map(Value::Function)
+                .inspect(|function| self.bind(f_name, *function)),
+            ("lambda", [RispExp::List(params), body @ ..]) => self
+                .define_function(list, None, params, body)
+                .map(Value::Function),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn pick_branch<'a>(
+        &mut self,
+        test_form: &RispExp,
+        branches: &'a [RispExp],
+    ) -> Result<Option<&'a RispExp>, RispErr> {
+        Ok(if self.eval(test_form)?.is_true() {
+            branches.first()
+        } else {
+            branches.get(1)
+        })
+    }
+
+    fn eval_while(&mut self, test_form: &RispExp, body: &[RispExp]) -> Result<Value, RispErr> {
+        while self.eval(test_form)?.is_true() {
+            for form in body.iter() {
+                self.eval(form)?;
+            }
+        }
+        Ok(Value::Null)
+    }
+
+    // the user function a call goes to, `None` for a builtin
+    fn callee(&mut self, first_form: &RispExp) -> Result<Option<usize>, RispErr> {
+        let value = match first_form {
+            RispExp::Symbol(name) => match self.lookup_mut(name) {
+                Some(value) => *value,
+                None => return Ok(None),
+            },
+            RispExp::List(_) => self.eval(first_form)?,
+            _ => Value::Null,
+        };
+        match value {
+            Value::Function(id) => Ok(Some(id)),
+            _ => Err(RispErr::Reason("first form must be a function".to_string())),
+        }
+    }
+
+    // reads the ((param type)...) -> type annotations left by type inference
+    fn define_function(
+        &mut self,
+        list: &[RispExp],
+        name: Option<&String>,
+        param_forms: &[RispExp],
+        body: &[RispExp],
+    ) -> Result<usize, RispErr> {
+        if let Some(id) = self.defined.get(&list.as_ptr()) {
+            return Ok(*id);
+        }
+
+        let params = param_forms
+            .iter()
+            .map(|param| match param {
+                RispExp::Symbol(s) => Ok(s.clone()),
+                RispExp::List(typed) => match &typed[..] {
+                    [RispExp::Symbol(s), RispExp::Symbol(_)] => Ok(s.clone()),
+                    _ => Err(RispErr::Reason(format!("invalid parameter {}", param))),
+                },
+                _ => Err(RispErr::Reason(format!("invalid parameter {}", param))),
+            })
+            .collect::<Result<Vec<String>, RispErr>>()?;
+        let (ret, body) = match body {
+            [RispExp::Symbol(arrow), RispExp::Symbol(ret), body @ ..] if arrow == "->" => (
+                Type::from_name(ret).ok_or(RispErr::Reason(format!("unknown type `{}`", ret)))?,
+                body,
+            ),
+            _ => (Type::Int, body),
+        };
+        if body.is_empty() {
+            return Err(RispErr::Reason("expected a function body".to_string()));
+        }
+
+        let id = self.functions.len();
+        self.functions.push(Rc::new(Function {
+            name: name.cloned(),
+            params,
+            ret,
+            body: body.to_vec(),
+        }));
+        self.defined.insert(list.as_ptr(), id);
+        Ok(id)
+    }
+
+    fn call(&mut self, id: usize, args: Vec<Value>) -> Result<Value, RispErr> {
+        if self.depth == MAX_DEPTH {
+            return Err(RispErr::Reason(format!(
+                "recursion deeper than {} calls, only tail calls are unlimited",
+                MAX_DEPTH
+            )));
+        }
+        self.depth += 1;
+        let result = self.run_call(id, args);
+        self.depth -= 1;
+        result
+    }
+
+    // like the compiled function, the body sees the globals, its own name and its parameters
+    fn run_call(&mut self, mut id: usize, mut args: Vec<Value>) -> Result<Value, RispErr> {
+        loop {
+            let function = Rc::clone(&self.functions[id]);
+            if args.len() != function.params.len() {
+                return Err(RispErr::Reason(format!(
+                    "function expects {} arguments, got {}",
+                    function.params.len(),
+                    args.len()
+                )));
+            }
+
+            let mut scope = HashMap::new();
+            if let Some(name) = &function.name {
+                scope.insert(name.clone(), Value::Function(id));
+            }
+            scope.extend(function.params.iter().cloned().zip(args));
+
+            let outer_scopes = self.scopes.split_off(1);
+            self.scopes.push(scope);
+            let result = self.eval_body(&function.body);
+            self.scopes.truncate(1);
+            self.scopes.extend(outer_scopes);
+
+            match result? {
+                // a body without a number returns zero
+                Tail::Value(Value::Null) => return Value::Int(0).convert(function.ret),
+                Tail::Value(value) => return Ok(value),
+                Tail::Call(next, next_args) => {
+                    id = next;
+                    args = next_args;
+                }
+            }
+        }
+    }
+
+    fn eval_body(&mut self, body: &[RispExp]) -> Result<Tail, RispErr> {
+        let (last, forms) = body
+            .split_last()
+            .ok_or_else(|| RispErr::Reason("expected a function body".to_string()))?;
+        for form in forms.iter() {
+            self.eval(form)?;
+        }
+        self.eval_tail(last)
+    }
+
+    fn eval_tail(&mut self, exp: &RispExp) -> Result<Tail, RispErr> {
+        if let RispExp::List(list) = exp {
+            match list.split_first() {
+                Some((RispExp::Symbol(s), [test_form, branches @ ..])) if s == "if" => {
+                    return match self.pick_branch(test_form, branches)? {
+                        Some(branch) => self.eval_tail(branch),
+                        None => Ok(Tail::Value(Value::Null)),
+                    };
+                }
+                Some((RispExp::Symbol(s), clauses)) if s == "cond" => {
+                    return self.eval_tail(&cond_to_if(clauses)?);
+                }
+                Some((RispExp::Symbol(s), _)) if is_special_form(s) => {}
+                Some((first_form, arg_forms)) => {
+                    if let Some(id) = self.callee(first_form)? {
+                        let args = arg_forms.iter().map(|x| self.eval(x)).collect::<Result<
+                            Vec<Value>,
+                            RispErr,
+                        >>(
+                        )?;
+                        return Ok(Tail::Call(id, args));
+                    }
+                }
+                None => {}
+            }
+        }
+
+        self.eval(exp).map(Tail::Value)
+    }
+
+    fn eval_builtin(&mut self, name: &str, args: &[Value]) -> Result<Value, RispErr> {
+        match (name, args) {
+            ("printf", [x, ..]) => {
+                println!("Result: {}", x);
+                Ok(*x)
+            }
+            ("printf", []) => Ok(Value::Null),
+            ("input", _) => self.read_input("an integer", |word| word.parse().map(Value::Int).ok()),
+            ("input-float", _) => {
+                self.read_input("a number", |word| word.parse().map(Value::Double).ok())
+            }
+            _ => eval_pure_builtin(name, args),
+        }
+    }
+
+    // like scanf, reads the next whitespace separated word
+    fn read_input(
+        &mut self,
+        expected: &str,
+        parse: fn(&str) -> Option<Value>,
+    ) -> Result<Value, RispErr> {
+        while self.input.is_empty() {
+            let mut line = String::new();
+            let read = io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|e| RispErr::Reason(format!("cannot read stdin: {}", e)))?;
+            if read == 0 {
+                return Err(RispErr::Reason("unexpected end of input".to_string()));
+            }
+            self.input
+                .extend(line.split_whitespace().map(|word| word.to_string()));
+        }
+
+        let word = self.input.pop_front().unwrap_or_default();
+        parse(&word).ok_or(RispErr::Reason(format!(
+            "expected {} as input, got `{}`",
+            expected, word
+        )))
+    }
+}
+
+// builtins without side effects, constant folding runs them ahead of time
+pub fn eval_pure_builtin(name: &str, args: &[Value]) -> Result<Value, RispErr> {
+    match (name, args) {
+        ("+", []) => Ok(Value::Int(0)),
+        ("*", []) => Ok(Value::Int(1)),
+        ("-", [x]) => arithmetic(name, Value::Int(0).convert(x.value_type())?, *x, SUB),
+        ("/", [x]) => arithmetic(name, Value::Int(1).convert(x.value_type())?, *x, DIV),
+        ("+", [first, rest @ ..]) => fold(name, *first, rest, ADD),
+        ("-", [first, rest @ ..]) => fold(name, *first, rest, SUB),
+        ("*", [first, rest @ ..]) => fold(name, *first, rest, MUL),
+        ("/", [first, rest @ ..]) => fold(name, *first, rest, DIV),
+        ("=", [a, b]) => compare(*a, *b, (|a, b| a == b, |a, b| a == b)),
+        ("<", [a, b]) => compare(*a, *b, (|a, b| a < b, |a, b| a < b)),
+        (">", [a, b]) => compare(*a, *b, (|a, b| a > b, |a, b| a > b)),
+        ("<=", [a, b]) => compare(*a, *b, (|a, b| a <= b, |a, b| a <= b)),
+        (">=", [a, b]) => compare(*a, *b, (|a, b| a >= b, |a, b| a >= b)),
+        ("float", [x]) => x.convert(Type::Double),
+        ("int", [x]) => x.convert(Type::Int),
+        ("long", [x]) => x.convert(Type::Long),
+        _ => Err(RispErr::Reason(format!(
+            "`{}` cannot be called with {} arguments",
+            name,
+            args.len()
+        ))),
+    }
+}
+
+fn fold(op: &str, first: Value, rest: &[Value], ops: (IntOp, FloatOp)) -> Result<Value, RispErr> {
+    rest.iter()
+        .try_fold(first, |lhs, rhs| arithmetic(op, lhs, *rhs, ops))
+}
+
+fn is_special_form(name: &str) -> bool {
+    matches!(
+        name,
+        "define" | "set!" | "if" | "cond" | "while" | "defn" | "lambda"
+    )
+}
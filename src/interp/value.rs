@@ -0,0 +1,125 @@
+use crate::infer::types::Type;
+use crate::RispErr;
+use std::fmt;
+
+// runtime values, with the same widths as the compiled program
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i32),
+    Long(i64),
+    Double(f64),
+    Function(usize), // index into the interpreter's functions
+}
+
+impl Value {
+    pub fn is_true(self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Function(_) => true,
+            Value::Bool(b) => b,
+            Value::Int(n) => n != 0,
+            Value::Long(n) => n != 0,
+            Value::Double(f) => f != 0.0,
+        }
+    }
+
+    // conversions behave like sitofp/fptosi and integer truncation
+    pub fn convert(self, to: Type) -> Result<Value, RispErr> {
+        let value = match (self, to) {
+            (Value::Null, _) | (Value::Function(_), _) => {
+                return Err(RispErr::Reason("expected a number".to_string()))
+            }
+            (Value::Double(f), Type::Double) => Value::Double(f),
+            (Value::Double(f), Type::Long) => Value::Long(f as i64),
+            (Value::Double(f), Type::Int) => Value::Int(f as i32),
+            (Value::Double(f), Type::Bool) => Value::Bool(f != 0.0),
+            (value, Type::Double) => Value::Double(value.as_i64() as f64),
+            (value, Type::Long) => Value::Long(value.as_i64()),
+            (value, Type::Int) => Value::Int(value.as_i64() as i32),
+            (value, Type::Bool) => Value::Bool(value.as_i64() != 0),
+        };
+        Ok(value)
+    }
+
+    pub fn value_type(self) -> Type {
+        match self {
+            Value::Bool(_) => Type::Bool,
+            Value::Long(_) => Type::Long,
+            Value::Double(_) => Type::Double,
+            Value::Int(_) | Value::Null | Value::Function(_) => Type::Int,
+        }
+    }
+
+    fn as_i64(self) -> i64 {
+        match self {
+            Value::Bool(b) => b as i64,
+            Value::Int(n) => n as i64,
+            Value::Long(n) => n,
+            Value::Double(f) => f as i64,
+            Value::Null | Value::Function(_) => 0,
+        }
+    }
+}
+
+pub type IntOp = fn(i64, i64) -> Option<i64>;
+pub type FloatOp = fn(f64, f64) -> f64;
+pub type IntTest = fn(i64, i64) -> bool;
+pub type FloatTest = fn(f64, f64) -> bool;
+
+// operands have the same type once inference has inserted the conversions
+pub fn arithmetic(
+    op: &str,
+    lhs: Value,
+    rhs: Value,
+    (int_op, float_op): (IntOp, FloatOp),
+) -> Result<Value, RispErr> {
+    let value = match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => int_op(a as i64, b as i64).map(|n| Value::Int(n as i32)),
+        (Value::Long(a), Value::Long(b)) => int_op(a, b).map(Value::Long),
+        (Value::Double(a), Value::Double(b)) => Some(Value::Double(float_op(a, b))),
+        _ => {
+            return Err(RispErr::Reason(format!(
+                "`{}` cannot mix {} and {}",
+                op, lhs, rhs
+            )))
+        }
+    };
+    value.ok_or(RispErr::Reason(format!("`{}` divides by zero", op)))
+}
+
+pub fn compare(
+    lhs: Value,
+    rhs: Value,
+    (int_test, float_test): (IntTest, FloatTest),
+) -> Result<Value, RispErr> {
+    match (lhs, rhs) {
+        (Value::Double(a), Value::Double(b)) => Ok(Value::Bool(float_test(a, b))),
+        (
+            Value::Bool(_) | Value::Int(_) | Value::Long(_),
+            Value::Bool(_) | Value::Int(_) | Value::Long(_),
+        ) => Ok(Value::Bool(int_test(lhs.as_i64(), rhs.as_i64()))),
+        _ => Err(RispErr::Reason(format!(
+            "comparison cannot mix {} and {}",
+            lhs, rhs
+        ))),
+    }
+}
+
+// matches the formats `eval_printf_fn` passes to printf
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", *b as i32),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Long(n) => write!(f, "{}", n),
+            // spelled like printf does, with its sign bit
+            Value::Double(x) if x.is_nan() && x.is_sign_negative() => write!(f, "-nan"),
+            Value::Double(x) if x.is_nan() => write!(f, "nan"),
+            Value::Double(x) => write!(f, "{:.6}", x),
+            Value::Function(_) => write!(f, "function"),
+        }
+    }
+}
@@ -1,11 +1,26 @@
+use crate::ir::context::{Builder, Context};
 use llvm_sys::core::*;
 use llvm_sys::*;
 
 #[allow(dead_code)]
 pub fn append_basic_block_in_context(
-    context: *mut LLVMContext,
+    context: &Context,
     function: *mut LLVMValue,
     function_name: &str,
 ) -> *mut LLVMBasicBlock {
-    unsafe { LLVMAppendBasicBlockInContext(context, function, c_string!(function_name).as_ptr()) }
+    unsafe {
+        LLVMAppendBasicBlockInContext(
+            context.as_raw(),
+            function,
+            c_string!(function_name).as_ptr(),
+        )
+    }
+}
+
+pub fn get_insert_block(builder: &Builder) -> *mut LLVMBasicBlock {
+    unsafe { LLVMGetInsertBlock(builder.as_raw()) }
+}
+
+pub fn get_basic_block_parent(block: *mut LLVMBasicBlock) -> *mut LLVMValue {
+    unsafe { LLVMGetBasicBlockParent(block) }
 }
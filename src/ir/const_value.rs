@@ -15,3 +15,8 @@ pub fn const_neg(value: *mut LLVMValue) -> *mut LLVMValue {
 pub fn const_int_signed(llvm_type: *mut LLVMType, value: u64) -> *mut LLVMValue {
     unsafe { LLVMConstInt(llvm_type, value, 0) }
 }
+
+#[allow(dead_code)]
+pub fn const_real(llvm_type: *mut LLVMType, value: f64) -> *mut LLVMValue {
+    unsafe { LLVMConstReal(llvm_type, value) }
+}
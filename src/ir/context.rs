@@ -0,0 +1,94 @@
+use llvm_sys::core::{
+    LLVMContextCreate, LLVMContextDispose, LLVMCreateBuilderInContext, LLVMDisposeBuilder,
+    LLVMDisposeModule, LLVMModuleCreateWithNameInContext,
+};
+use llvm_sys::prelude::{LLVMBuilderRef, LLVMContextRef, LLVMModuleRef};
+use std::marker::PhantomData;
+use std::mem;
+
+// owns the types, constants and modules of one compilation and disposes them when
+// dropped; modules and builders borrow it so none of them outlives it
+pub struct Context {
+    raw: LLVMContextRef,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context {
+            raw: unsafe { LLVMContextCreate() },
+        }
+    }
+
+    pub fn as_raw(&self) -> LLVMContextRef {
+        self.raw
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { LLVMContextDispose(self.raw) }
+    }
+}
+
+// a module that is disposed when dropped, unless `into_raw` handed it over to LLVM
+pub struct Module<'ctx> {
+    raw: LLVMModuleRef,
+    _context: PhantomData<&'ctx Context>,
+}
+
+impl<'ctx> Module<'ctx> {
+    pub fn new(context: &'ctx Context, name: &str) -> Self {
+        let raw =
+            unsafe { LLVMModuleCreateWithNameInContext(c_string!(name).as_ptr(), context.raw) };
+        unsafe { Module::from_raw(context, raw) }
+    }
+
+    // takes ownership of a module LLVM created in `context`, e.g. by parsing one
+    pub unsafe fn from_raw(_context: &'ctx Context, raw: LLVMModuleRef) -> Self {
+        Module {
+            raw,
+            _context: PhantomData,
+        }
+    }
+
+    pub fn as_raw(&self) -> LLVMModuleRef {
+        self.raw
+    }
+
+    // for the linker and the execution engine, which dispose the module themselves
+    pub fn into_raw(self) -> LLVMModuleRef {
+        let raw = self.raw;
+        mem::forget(self);
+        raw
+    }
+}
+
+impl Drop for Module<'_> {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeModule(self.raw) }
+    }
+}
+
+pub struct Builder<'ctx> {
+    raw: LLVMBuilderRef,
+    _context: PhantomData<&'ctx Context>,
+}
+
+impl<'ctx> Builder<'ctx> {
+    pub fn new(context: &'ctx Context) -> Self {
+        Builder {
+            raw: unsafe { LLVMCreateBuilderInContext(context.raw) },
+            _context: PhantomData,
+        }
+    }
+
+    pub fn as_raw(&self) -> LLVMBuilderRef {
+        self.raw
+    }
+}
+
+impl Drop for Builder<'_> {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeBuilder(self.raw) }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::ir::context::Context;
+use llvm_sys::core::{
+    LLVMContextGetDiagnosticContext, LLVMContextGetDiagnosticHandler,
+    LLVMContextSetDiagnosticHandler, LLVMDisposeMessage, LLVMGetDiagInfoDescription,
+    LLVMGetDiagInfoSeverity,
+};
+use llvm_sys::prelude::LLVMDiagnosticInfoRef;
+use llvm_sys::LLVMDiagnosticSeverity::LLVMDSError;
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+
+// copies a string LLVM allocated and frees it
+pub fn take_message(message: *mut c_char) -> String {
+    let text = unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned();
+    unsafe { LLVMDisposeMessage(message) };
+    text
+}
+
+extern "C" fn collect_error(info: LLVMDiagnosticInfoRef, errors: *mut c_void) {
+    let errors = unsafe { &mut *(errors as *mut Vec<String>) };
+    let message = unsafe {
+        let description = LLVMGetDiagInfoDescription(info);
+        let message = CStr::from_ptr(description).to_string_lossy().into_owned();
+        LLVMDisposeMessage(description);
+        message
+    };
+
+    if unsafe { LLVMGetDiagInfoSeverity(info) } == LLVMDSError {
+        errors.push(message);
+    } else {
+        eprintln!("warning: {}", message);
+    }
+}
+
+// runs `f` with the errors LLVM reports on `context` collected, instead of printed
+// before LLVM exits the process
+pub fn capture_errors<T>(context: &Context, f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let mut errors: Vec<String> = vec![];
+    unsafe {
+        let context = context.as_raw();
+        let handler = LLVMContextGetDiagnosticHandler(context);
+        let handler_context = LLVMContextGetDiagnosticContext(context);
+        LLVMContextSetDiagnosticHandler(
+            context,
+            Some(collect_error),
+            &mut errors as *mut Vec<String> as *mut c_void,
+        );
+        let result = f();
+        LLVMContextSetDiagnosticHandler(context, handler, handler_context);
+        (result, errors)
+    }
+}
@@ -1,25 +1,25 @@
-use crate::builtin::env::RispEnv;
+use crate::ir::context::{Builder, Context};
 use llvm_sys::core::*;
 use llvm_sys::*;
 
 #[allow(dead_code)]
-pub fn int32_type() -> *mut LLVMType {
-    unsafe { LLVMInt32Type() }
+pub fn int32_type(context: &Context) -> *mut LLVMType {
+    unsafe { LLVMInt32TypeInContext(context.as_raw()) }
 }
 
 #[allow(dead_code)]
-pub fn int32_type_in_context(env: &RispEnv) -> *mut LLVMType {
-    unsafe { LLVMInt32TypeInContext(env.llvm_context) }
+pub fn int64_type(context: &Context) -> *mut LLVMType {
+    unsafe { LLVMInt64TypeInContext(context.as_raw()) }
 }
 
 #[allow(dead_code)]
-pub fn int8_type() -> *mut LLVMType {
-    unsafe { LLVMInt8Type() }
+pub fn int8_type(context: &Context) -> *mut LLVMType {
+    unsafe { LLVMInt8TypeInContext(context.as_raw()) }
 }
 
 #[allow(dead_code)]
-pub fn int1_type() -> *mut LLVMType {
-    unsafe { LLVMInt1Type() }
+pub fn int1_type(context: &Context) -> *mut LLVMType {
+    unsafe { LLVMInt1TypeInContext(context.as_raw()) }
 }
 
 #[allow(dead_code)]
@@ -28,8 +28,8 @@ pub fn array_type(llvm_type: *mut LLVMType, length: u32) -> *mut LLVMType {
 }
 
 #[allow(dead_code)]
-pub fn pointer_type() -> *mut LLVMType {
-    unsafe { LLVMPointerType(int8_type(), 0) }
+pub fn pointer_type(context: &Context) -> *mut LLVMType {
+    unsafe { LLVMPointerType(int8_type(context), 0) }
 }
 
 #[allow(dead_code)]
@@ -49,15 +49,36 @@ pub fn type_of(value: *mut LLVMValue) -> *mut LLVMType {
 
 #[allow(dead_code)]
 pub fn cast_type(
-    builder: *mut LLVMBuilder,
+    builder: &Builder,
     value: *mut LLVMValue,
     dest_type: *mut LLVMType,
     name: &str,
 ) -> *mut LLVMValue {
-    unsafe { LLVMBuildBitCast(builder, value, dest_type, c_string!(name).as_ptr()) }
+    unsafe { LLVMBuildBitCast(builder.as_raw(), value, dest_type, c_string!(name).as_ptr()) }
 }
 
 #[allow(dead_code)]
-pub fn void_type() -> *mut LLVMType {
-    unsafe { LLVMVoidType() }
+pub fn void_type(context: &Context) -> *mut LLVMType {
+    unsafe { LLVMVoidTypeInContext(context.as_raw()) }
+}
+
+#[allow(dead_code)]
+pub fn double_type(context: &Context) -> *mut LLVMType {
+    unsafe { LLVMDoubleTypeInContext(context.as_raw()) }
+}
+
+#[allow(dead_code)]
+pub fn element_type(llvm_type: *mut LLVMType) -> *mut LLVMType {
+    unsafe { LLVMGetElementType(llvm_type) }
+}
+
+#[allow(dead_code)]
+pub fn is_double_type(llvm_type: *mut LLVMType) -> bool {
+    unsafe { LLVMGetTypeKind(llvm_type) == LLVMTypeKind::LLVMDoubleTypeKind }
+}
+
+// bits of an integer type, whichever context it was created in
+#[allow(dead_code)]
+pub fn int_width(llvm_type: *mut LLVMType) -> u32 {
+    unsafe { LLVMGetIntTypeWidth(llvm_type) }
 }
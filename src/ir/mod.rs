@@ -1,5 +1,7 @@
 pub mod block;
 pub mod const_value;
+pub mod context;
+pub mod diagnostic;
 pub mod llvm_type;
 pub mod operate;
 pub mod scope;
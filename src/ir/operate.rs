@@ -1,73 +1,216 @@
 use llvm_sys::core::*;
 use llvm_sys::*;
 
+use crate::ir::context::{Builder, Context, Module};
 use crate::ir::llvm_type::int32_type;
 use crate::ir::string::const_int;
 use crate::RispEnv;
 use std::os::raw::c_char;
 
 #[allow(dead_code)]
-pub fn build_ret(builder: *mut LLVMBuilder, llvm_value: *mut LLVMValue) -> *mut LLVMValue {
-    unsafe { LLVMBuildRet(builder, llvm_value) }
+pub fn build_ret(builder: &Builder, llvm_value: *mut LLVMValue) -> *mut LLVMValue {
+    unsafe { LLVMBuildRet(builder.as_raw(), llvm_value) }
 }
 
 #[allow(dead_code)]
-pub fn build_const_gep(llvm_const_value: *mut LLVMValue) -> *mut LLVMValue {
-    let mut args = vec![const_int(int32_type(), 0), const_int(int32_type(), 0)];
+pub fn build_const_gep(context: &Context, llvm_const_value: *mut LLVMValue) -> *mut LLVMValue {
+    let mut args = vec![
+        const_int(int32_type(context), 0),
+        const_int(int32_type(context), 0),
+    ];
     unsafe { LLVMConstInBoundsGEP(llvm_const_value, args.as_mut_ptr(), args.len() as u32) }
 }
 
 #[allow(dead_code)]
-pub fn build_position_at_end(builder: *mut LLVMBuilder, block: *mut LLVMBasicBlock) {
+pub fn build_position_at_end(builder: &Builder, block: *mut LLVMBasicBlock) {
     unsafe {
-        LLVMPositionBuilderAtEnd(builder, block);
+        LLVMPositionBuilderAtEnd(builder.as_raw(), block);
     };
 }
 
 #[allow(dead_code)]
-pub fn build_alloca(
-    builder: *mut LLVMBuilder,
+pub fn build_alloca(builder: &Builder, llvm_type: *mut LLVMType, name: &str) -> *mut LLVMValue {
+    unsafe { LLVMBuildAlloca(builder.as_raw(), llvm_type, c_string!(name).as_ptr()) }
+}
+
+// allocas in the entry block are reserved once per call instead of once per loop iteration
+pub fn build_entry_alloca(
+    builder: &Builder,
     llvm_type: *mut LLVMType,
     name: &str,
 ) -> *mut LLVMValue {
-    unsafe { LLVMBuildAlloca(builder, llvm_type, c_string!(name).as_ptr()) }
+    unsafe {
+        let current_block = LLVMGetInsertBlock(builder.as_raw());
+        let entry_block = LLVMGetEntryBasicBlock(LLVMGetBasicBlockParent(current_block));
+        let first_instruction = LLVMGetFirstInstruction(entry_block);
+        if first_instruction.is_null() {
+            LLVMPositionBuilderAtEnd(builder.as_raw(), entry_block);
+        } else {
+            LLVMPositionBuilderBefore(builder.as_raw(), first_instruction);
+        }
+
+        let alloca = LLVMBuildAlloca(builder.as_raw(), llvm_type, c_string!(name).as_ptr());
+        LLVMPositionBuilderAtEnd(builder.as_raw(), current_block);
+        alloca
+    }
 }
 
 #[allow(dead_code)]
 pub fn build_store(
-    builder: *mut LLVMBuilder,
+    builder: &Builder,
     value: *mut LLVMValue,
     target: *mut LLVMValue,
 ) -> *mut LLVMValue {
-    unsafe { LLVMBuildStore(builder, value, target) }
+    unsafe { LLVMBuildStore(builder.as_raw(), value, target) }
+}
+
+pub fn build_load(builder: &Builder, llvm_value: *mut LLVMValue, name: &str) -> *mut LLVMValue {
+    unsafe { LLVMBuildLoad(builder.as_raw(), llvm_value, c_string!(name).as_ptr()) }
+}
+
+pub fn build_icmp(
+    builder: &Builder,
+    predicate: LLVMIntPredicate,
+    lhs: *mut LLVMValue,
+    rhs: *mut LLVMValue,
+    name: &str,
+) -> *mut LLVMValue {
+    unsafe {
+        LLVMBuildICmp(
+            builder.as_raw(),
+            predicate,
+            lhs,
+            rhs,
+            c_string!(name).as_ptr(),
+        )
+    }
+}
+
+pub fn build_fcmp(
+    builder: &Builder,
+    predicate: LLVMRealPredicate,
+    lhs: *mut LLVMValue,
+    rhs: *mut LLVMValue,
+    name: &str,
+) -> *mut LLVMValue {
+    unsafe {
+        LLVMBuildFCmp(
+            builder.as_raw(),
+            predicate,
+            lhs,
+            rhs,
+            c_string!(name).as_ptr(),
+        )
+    }
 }
 
-pub fn build_load(
-    builder: *mut LLVMBuilder,
-    llvm_value: *mut LLVMValue,
+pub fn build_sitofp(
+    builder: &Builder,
+    value: *mut LLVMValue,
+    dest_type: *mut LLVMType,
     name: &str,
 ) -> *mut LLVMValue {
-    unsafe { LLVMBuildLoad(builder, llvm_value, c_string!(name).as_ptr()) }
+    unsafe { LLVMBuildSIToFP(builder.as_raw(), value, dest_type, c_string!(name).as_ptr()) }
 }
 
-pub fn build_int32_value(value: f64) -> *mut LLVMValue {
-    unsafe { LLVMConstInt(LLVMInt32Type(), value as u64, 0) }
+pub fn build_fptosi(
+    builder: &Builder,
+    value: *mut LLVMValue,
+    dest_type: *mut LLVMType,
+    name: &str,
+) -> *mut LLVMValue {
+    unsafe { LLVMBuildFPToSI(builder.as_raw(), value, dest_type, c_string!(name).as_ptr()) }
+}
+
+pub fn build_uitofp(
+    builder: &Builder,
+    value: *mut LLVMValue,
+    dest_type: *mut LLVMType,
+    name: &str,
+) -> *mut LLVMValue {
+    unsafe { LLVMBuildUIToFP(builder.as_raw(), value, dest_type, c_string!(name).as_ptr()) }
+}
+
+// sign or zero extends, or truncates, to `dest_type`
+pub fn build_int_cast(
+    builder: &Builder,
+    value: *mut LLVMValue,
+    dest_type: *mut LLVMType,
+    signed: bool,
+    name: &str,
+) -> *mut LLVMValue {
+    unsafe {
+        LLVMBuildIntCast2(
+            builder.as_raw(),
+            value,
+            dest_type,
+            signed as i32,
+            c_string!(name).as_ptr(),
+        )
+    }
+}
+
+pub fn build_br(builder: &Builder, block: *mut LLVMBasicBlock) -> *mut LLVMValue {
+    unsafe { LLVMBuildBr(builder.as_raw(), block) }
+}
+
+pub fn build_cond_br(
+    builder: &Builder,
+    condition: *mut LLVMValue,
+    then_block: *mut LLVMBasicBlock,
+    else_block: *mut LLVMBasicBlock,
+) -> *mut LLVMValue {
+    unsafe { LLVMBuildCondBr(builder.as_raw(), condition, then_block, else_block) }
+}
+
+pub fn build_phi(builder: &Builder, llvm_type: *mut LLVMType, name: &str) -> *mut LLVMValue {
+    unsafe { LLVMBuildPhi(builder.as_raw(), llvm_type, c_string!(name).as_ptr()) }
+}
+
+pub fn add_incoming(
+    phi: *mut LLVMValue,
+    mut values: Vec<*mut LLVMValue>,
+    mut blocks: Vec<*mut LLVMBasicBlock>,
+) {
+    unsafe {
+        LLVMAddIncoming(
+            phi,
+            values.as_mut_ptr(),
+            blocks.as_mut_ptr(),
+            values.len() as u32,
+        )
+    }
+}
+
+#[allow(dead_code)]
+pub fn build_int32_value(context: &Context, value: f64) -> *mut LLVMValue {
+    unsafe {
+        LLVMConstInt(
+            LLVMInt32TypeInContext(context.as_raw()),
+            value as i64 as u64,
+            0,
+        )
+    }
 }
 
 #[allow(dead_code)]
 pub fn llvm_int_value(env: &RispEnv, name: *const c_char, value: f64) -> *mut LLVMValue {
     let val = unsafe {
         LLVMBuildAlloca(
-            env.llvm_builder,
-            LLVMInt32TypeInContext(env.llvm_context),
+            env.llvm_builder.as_raw(),
+            LLVMInt32TypeInContext(env.llvm_context.as_raw()),
             name,
         )
     };
 
     unsafe {
         LLVMBuildStore(
-            env.llvm_builder,
-            LLVMConstInt(LLVMInt32TypeInContext(env.llvm_context), value as u64, 0),
+            env.llvm_builder.as_raw(),
+            LLVMConstInt(
+                LLVMInt32TypeInContext(env.llvm_context.as_raw()),
+                value as u64,
+                0,
+            ),
             val,
         );
     }
@@ -76,22 +219,36 @@ pub fn llvm_int_value(env: &RispEnv, name: *const c_char, value: f64) -> *mut LL
 }
 
 pub fn add_function(
-    target_module: *mut LLVMModule,
+    target_module: &Module,
     function_type: *mut LLVMType,
     name: &str,
 ) -> *mut LLVMValue {
-    unsafe { LLVMAddFunction(target_module, c_string!(name).as_ptr(), function_type) }
+    unsafe {
+        LLVMAddFunction(
+            target_module.as_raw(),
+            c_string!(name).as_ptr(),
+            function_type,
+        )
+    }
+}
+
+pub fn get_param(function: *mut LLVMValue, index: usize) -> *mut LLVMValue {
+    unsafe { LLVMGetParam(function, index as u32) }
+}
+
+pub fn set_tail_call(call: *mut LLVMValue) {
+    unsafe { LLVMSetTailCall(call, 1) }
 }
 
 pub fn call_function(
-    builder: *mut LLVMBuilder,
+    builder: &Builder,
     function: *mut LLVMValue,
     mut args: Vec<*mut LLVMValue>,
     name: &str,
 ) -> *mut LLVMValue {
     unsafe {
         LLVMBuildCall(
-            builder,
+            builder.as_raw(),
             function,
             args.as_mut_ptr(),
             args.len() as u32,
@@ -1,9 +1,19 @@
+use crate::ir::context::Module;
 use llvm_sys::core::*;
 use llvm_sys::*;
 
 #[allow(dead_code)]
-pub fn add_global(module: *mut LLVMModule, llvm_type: *mut LLVMType, name: &str) -> *mut LLVMValue {
-    unsafe { LLVMAddGlobal(module, llvm_type, c_string!(name).as_ptr()) }
+pub fn add_global(module: &Module, llvm_type: *mut LLVMType, name: &str) -> *mut LLVMValue {
+    unsafe { LLVMAddGlobal(module.as_raw(), llvm_type, c_string!(name).as_ptr()) }
+}
+
+pub fn get_named_global(module: &Module, name: &str) -> Option<*mut LLVMValue> {
+    let global = unsafe { LLVMGetNamedGlobal(module.as_raw(), c_string!(name).as_ptr()) };
+    if global.is_null() {
+        None
+    } else {
+        Some(global)
+    }
 }
 
 #[allow(dead_code)]
@@ -34,3 +44,78 @@ pub fn set_unnamed_address(value: *mut LLVMValue) {
         LLVMSetUnnamedAddress(value, name);
     }
 }
+
+pub fn value_name(value: *mut LLVMValue) -> String {
+    let mut length = 0;
+    let name = unsafe { LLVMGetValueName2(value, &mut length) };
+    let bytes = unsafe { std::slice::from_raw_parts(name as *const u8, length) };
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+pub fn set_value_name(value: *mut LLVMValue, name: &str) {
+    unsafe { LLVMSetValueName2(value, c_string!(name).as_ptr(), name.len()) }
+}
+
+// declaration in `module` of a global defined in another module
+pub fn declare_global(module: &Module, global: *mut LLVMValue) -> *mut LLVMValue {
+    let name = value_name(global);
+    get_named_global(module, &name)
+        .unwrap_or_else(|| add_global(module, unsafe { LLVMGlobalGetValueType(global) }, &name))
+}
+
+pub fn get_named_function(module: &Module, name: &str) -> Option<*mut LLVMValue> {
+    let function = unsafe { LLVMGetNamedFunction(module.as_raw(), c_string!(name).as_ptr()) };
+    if function.is_null() {
+        None
+    } else {
+        Some(function)
+    }
+}
+
+// declaration in `module` of a function defined in another module
+pub fn declare_function(module: &Module, function: *mut LLVMValue) -> *mut LLVMValue {
+    let name = value_name(function);
+    get_named_function(module, &name).unwrap_or_else(|| unsafe {
+        LLVMAddFunction(
+            module.as_raw(),
+            c_string!(name).as_ptr(),
+            LLVMGlobalGetValueType(function),
+        )
+    })
+}
+
+// functions and global variables that `module` defines rather than declares
+pub fn defined_globals(module: &Module) -> Vec<*mut LLVMValue> {
+    let mut values = vec![];
+    unsafe {
+        let mut function = LLVMGetFirstFunction(module.as_raw());
+        while !function.is_null() {
+            values.push(function);
+            function = LLVMGetNextFunction(function);
+        }
+        let mut global = LLVMGetFirstGlobal(module.as_raw());
+        while !global.is_null() {
+            values.push(global);
+            global = LLVMGetNextGlobal(global);
+        }
+    }
+    values
+        .into_iter()
+        .filter(|value| unsafe { LLVMIsDeclaration(*value) } == 0)
+        .collect()
+}
+
+// functions `module` calls but leaves for the linker to provide
+pub fn imported_functions(module: &Module) -> Vec<*mut LLVMValue> {
+    let mut functions = vec![];
+    unsafe {
+        let mut function = LLVMGetFirstFunction(module.as_raw());
+        while !function.is_null() {
+            if LLVMIsDeclaration(function) != 0 && !LLVMGetFirstUse(function).is_null() {
+                functions.push(function);
+            }
+            function = LLVMGetNextFunction(function);
+        }
+    }
+    functions
+}
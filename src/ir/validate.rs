@@ -1,20 +1,25 @@
+use crate::ir::context::Module;
+use crate::ir::diagnostic::take_message;
+use crate::RispErr;
 use llvm_sys::analysis::{LLVMVerifierFailureAction, LLVMVerifyModule};
-use llvm_sys::*;
+use std::ptr::null_mut;
 
 const LLVM_ERROR: i32 = 1;
 
-#[allow(dead_code)]
-pub fn validate_module(module: *mut LLVMModule) {
-    let mut error = 0 as *mut i8;
-    let buf: *mut *mut i8 = &mut error;
+// checks the generated IR, the error carries the verifier's message
+pub fn validate_module(module: &Module) -> Result<(), RispErr> {
+    let mut error = null_mut();
     let ok = unsafe {
         LLVMVerifyModule(
-            module,
+            module.as_raw(),
             LLVMVerifierFailureAction::LLVMReturnStatusAction,
-            buf,
+            &mut error,
         )
     };
+    // LLVM allocates the message even when it is empty
+    let message = take_message(error);
     if ok == LLVM_ERROR {
-        panic!("cannot verify module.\nError: {}", string_from_raw!(error));
+        return Err(RispErr::Verify(message.trim_end().to_string()));
     }
+    Ok(())
 }
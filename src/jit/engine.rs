@@ -0,0 +1,79 @@
+use crate::ir::context::{Context, Module};
+use crate::ir::diagnostic::take_message;
+use crate::target::machine::initialize_native_target;
+use crate::RispErr;
+use llvm_sys::execution_engine::{
+    LLVMAddModule, LLVMCreateMCJITCompilerForModule, LLVMDisposeExecutionEngine,
+    LLVMExecutionEngineRef, LLVMGetFunctionAddress, LLVMInitializeMCJITCompilerOptions,
+    LLVMLinkInMCJIT, LLVMMCJITCompilerOptions,
+};
+use llvm_sys::support::LLVMLoadLibraryPermanently;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::{size_of, MaybeUninit};
+use std::ptr::{null, null_mut};
+
+extern "C" {
+    fn fflush(stream: *mut c_void) -> i32;
+}
+
+// compiled code prints through C's stdio, which is buffered apart from Rust's stdout
+pub fn flush_c_stdout() {
+    unsafe { fflush(null_mut()) };
+}
+
+// compiles modules to native code in-process, the engine owns every module added to it
+pub struct Jit<'ctx> {
+    engine: LLVMExecutionEngineRef,
+    _context: PhantomData<&'ctx Context>,
+}
+
+impl<'ctx> Jit<'ctx> {
+    pub fn new(module: Module<'ctx>) -> Result<Self, RispErr> {
+        let mut engine = null_mut();
+        let mut error: *mut i8 = null_mut();
+        let failed = unsafe {
+            LLVMLinkInMCJIT();
+            initialize_native_target()?;
+            // lets the generated code call printf and scanf from the host libc
+            LLVMLoadLibraryPermanently(null());
+
+            let mut options = MaybeUninit::<LLVMMCJITCompilerOptions>::zeroed();
+            let size = size_of::<LLVMMCJITCompilerOptions>();
+            LLVMInitializeMCJITCompilerOptions(options.as_mut_ptr(), size);
+            LLVMCreateMCJITCompilerForModule(
+                &mut engine,
+                module.into_raw(),
+                options.as_mut_ptr(),
+                size,
+                &mut error,
+            )
+        };
+
+        if failed != 0 {
+            return Err(RispErr::Reason(take_message(error)));
+        }
+        Ok(Jit {
+            engine,
+            _context: PhantomData,
+        })
+    }
+
+    // symbols the module leaves undefined resolve to modules added earlier
+    pub fn add_module(&self, module: Module<'ctx>) {
+        unsafe { LLVMAddModule(self.engine, module.into_raw()) }
+    }
+
+    pub fn function_address(&self, name: &str) -> Result<u64, RispErr> {
+        match unsafe { LLVMGetFunctionAddress(self.engine, c_string!(name).as_ptr()) } {
+            0 => Err(RispErr::Reason(format!("cannot find function `{}`", name))),
+            address => Ok(address),
+        }
+    }
+}
+
+impl Drop for Jit<'_> {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeExecutionEngine(self.engine) }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod engine;
+pub mod repl;
+pub mod run;
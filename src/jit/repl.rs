@@ -0,0 +1,204 @@
+use crate::builtin::env::{default_env, RispEnv};
+use crate::builtin::ir::{convert_number, number_type, value_or_zero};
+use crate::infer::pass::{infer_entry, Globals};
+use crate::infer::types::Type;
+use crate::interp::value::Value;
+use crate::ir::block::append_basic_block_in_context;
+use crate::ir::context::Context;
+use crate::ir::llvm_type::{cast_type, function_type, int64_type, is_double_type, type_of};
+use crate::ir::operate::{add_function, build_position_at_end, build_ret};
+use crate::ir::scope::{defined_globals, set_value_name, value_name};
+use crate::ir::validate::validate_module;
+use crate::jit::engine::{flush_c_stdout, Jit};
+use crate::optimize::fold::fold_constants;
+use crate::{eval_forms, parse_program, tokenize, RispErr, RispExp};
+use llvm_sys::LLVMValue;
+use std::collections::HashSet;
+use std::ffi::{c_void, CStr};
+use std::io::{self, Write};
+use std::mem::transmute;
+use std::os::raw::c_char;
+
+// C's stdin stream, each libc exports it under its own name
+extern "C" {
+    #[cfg(target_os = "linux")]
+    #[link_name = "stdin"]
+    static c_stdin: *mut c_void;
+    #[cfg(target_os = "macos")]
+    #[link_name = "__stdinp"]
+    static c_stdin: *mut c_void;
+}
+
+extern "C" {
+    fn fgets(buf: *mut c_char, size: i32, stream: *mut c_void) -> *mut c_char;
+    fn isatty(fd: i32) -> i32;
+}
+
+// state that outlives a single entry
+struct Session {
+    globals: Globals,
+    // every symbol handed to the engine, a name cannot be defined twice
+    symbols: HashSet<String>,
+    entries: usize,
+}
+
+// reads entries until end of input, an entry ends once its parentheses are balanced
+pub fn repl() -> Result<(), RispErr> {
+    let context = Context::new();
+    let env = &mut default_env(&context);
+    // starts with the module that only declares printf and scanf, every entry is
+    // compiled into a module of its own and added once it compiled
+    let jit = Jit::new(env.begin_module("entry.1"))?;
+    let mut session = Session {
+        globals: Globals::default(),
+        symbols: HashSet::new(),
+        entries: 0,
+    };
+    let interactive = unsafe { isatty(0) } != 0;
+
+    let mut source = String::new();
+    loop {
+        if interactive {
+            print!("{}", if source.is_empty() { "> " } else { ". " });
+            io::stdout().flush().ok();
+        }
+        match read_line() {
+            Some(line) => source.push_str(&line),
+            None => return Ok(()),
+        }
+
+        let tokens = tokenize(&source);
+        let depth = |paren: &str| tokens.iter().filter(|token| *token == paren).count();
+        if depth("(") > depth(")") {
+            continue;
+        }
+        if !tokens.is_empty() {
+            match session.run(env, &jit, &tokens) {
+                Ok(Some(result)) => println!("{}", result),
+                Ok(None) => {}
+                Err(e) => eprintln!("error: {}", e),
+            }
+        }
+        source.clear();
+    }
+}
+
+// reads through C's stdin so that `input` sees whatever follows the entry
+fn read_line() -> Option<String> {
+    let mut line: Vec<u8> = vec![];
+    let mut buf = [0 as c_char; 1024];
+    loop {
+        let read = unsafe { fgets(buf.as_mut_ptr(), buf.len() as i32, c_stdin) };
+        if read.is_null() {
+            break;
+        }
+        let chunk = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_bytes();
+        line.extend_from_slice(chunk);
+        if chunk.ends_with(b"\n") {
+            break;
+        }
+    }
+
+    if line.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+impl Session {
+    // compiles the entry into a module of its own, runs it and describes its result
+    fn run(
+        &mut self,
+        env: &mut RispEnv,
+        jit: &Jit,
+        tokens: &[String],
+    ) -> Result<Option<String>, RispErr> {
+        let (mut forms, globals) = infer_entry(&parse_program(tokens)?, &self.globals)?;
+        if env.fold {
+            forms = fold_constants(&forms);
+        }
+
+        self.entries += 1;
+        let name = format!("entry.{}", self.entries);
+        let next = format!("entry.{}", self.entries + 1);
+        let data = env.data.clone();
+        let (function, exp) = match compile_entry(env, &name, &forms) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                env.data = data;
+                env.scopes.clear();
+                // drops the half-generated module
+                env.begin_module(&next);
+                return Err(e);
+            }
+        };
+
+        for value in defined_globals(&env.llvm_module) {
+            let base = value_name(value);
+            let mut unique = base.clone();
+            let mut suffix = 0;
+            while self.symbols.contains(&unique) {
+                suffix += 1;
+                unique = format!("{}.{}", base, suffix);
+            }
+            if unique != base {
+                set_value_name(value, &unique);
+            }
+            self.symbols.insert(value_name(value));
+        }
+        jit.add_module(env.begin_module(&next));
+        self.globals = globals;
+
+        let address = jit.function_address(&value_name(function))?;
+        let bits = unsafe {
+            let entry: extern "C" fn() -> i64 = transmute(address);
+            entry()
+        };
+        flush_c_stdout();
+
+        let result = match exp {
+            RispExp::Number(_) => {
+                let value = match Type::from_llvm(number_type(env.llvm_context, &exp)) {
+                    Type::Bool => Value::Bool(bits != 0),
+                    Type::Int => Value::Int(bits as i32),
+                    Type::Long => Value::Long(bits),
+                    Type::Double => Value::Double(f64::from_bits(bits as u64)),
+                };
+                Some(value.to_string())
+            }
+            RispExp::Lambda(_, _, _) => Some(exp.to_string()),
+            _ => None,
+        };
+        Ok(result)
+    }
+}
+
+// a function returning the last value as i64, doubles are passed by their bits
+fn compile_entry(
+    env: &mut RispEnv,
+    name: &str,
+    forms: &[RispExp],
+) -> Result<(*mut LLVMValue, RispExp), RispErr> {
+    let function = add_function(
+        &env.llvm_module,
+        function_type(int64_type(env.llvm_context), &mut []),
+        name,
+    );
+    let block = append_basic_block_in_context(env.llvm_context, function, "entry");
+    build_position_at_end(&env.llvm_builder, block);
+
+    let exp = eval_forms(env, forms)?;
+    let value = value_or_zero(env.llvm_context, &exp);
+    let bits = if is_double_type(type_of(value)) {
+        cast_type(&env.llvm_builder, value, int64_type(env.llvm_context), "")
+    } else {
+        convert_number(env, value, int64_type(env.llvm_context), "")
+    };
+    build_ret(&env.llvm_builder, bits);
+    if env.verify {
+        validate_module(&env.llvm_module)?;
+    }
+
+    Ok((function, exp))
+}
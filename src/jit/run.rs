@@ -0,0 +1,22 @@
+use crate::builtin::env::default_env;
+use crate::ir::context::Context;
+use crate::jit::engine::{flush_c_stdout, Jit};
+use crate::{parse_eval, RispErr};
+use std::mem::transmute;
+
+// compiles the program and runs its `main` in-process, returns what `main` returns
+pub fn run(program: &str) -> Result<i32, RispErr> {
+    let context = Context::new();
+    let mut env = default_env(&context);
+    parse_eval(&mut env, program)?;
+
+    let jit = Jit::new(env.into_module())?;
+    let address = jit.function_address("main")?;
+    let code = unsafe {
+        let main: extern "C" fn() -> i32 = transmute(address);
+        main()
+    };
+    flush_c_stdout();
+
+    Ok(code)
+}
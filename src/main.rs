@@ -7,54 +7,87 @@ use std::ffi::CString;
 mod macros;
 
 mod builtin;
+mod cli;
+mod infer;
+mod interp;
 mod ir;
-
-use llvm_sys::core::*;
+mod jit;
+mod optimize;
+mod target;
 
 use std::fmt;
 use std::num::ParseFloatError;
 
-use llvm_sys::{LLVMModule, LLVMType, LLVMValue};
+use llvm_sys::LLVMValue;
 
-use crate::ir::llvm_type::{int32_type, int8_type, pointer_type};
-use crate::ir::operate::{add_function, build_ret};
+use crate::ir::context::{Context, Module};
+use crate::ir::llvm_type::{
+    double_type, function_type_var_arg, int32_type, int8_type, pointer_type,
+};
+use crate::ir::operate::{add_function, build_load, build_ret};
+use crate::ir::validate::validate_module;
 
 use crate::builtin::env::{default_env, RispEnv};
-use crate::builtin::ir::{eval_arithmetic, eval_input_fn, eval_number, eval_printf_fn};
+use crate::builtin::form::eval_special_form;
+use crate::builtin::ir::{
+    convert_number, eval_arithmetic, eval_call_fn, eval_input_fn, eval_number, eval_printf_fn,
+    value_or_zero,
+};
+use crate::cli::{build_cli, parse_command, read_source, Command, Emit, Target};
+use crate::infer::pass::infer_program;
+use crate::interp::eval::interpret;
+use crate::jit::repl::repl;
+use crate::jit::run::run;
+use crate::optimize::fold::fold_constants;
+use crate::optimize::pipeline::optimize_module;
+use crate::target::glue::emit_js_glue;
+use crate::target::link::link_executable;
+use crate::target::machine::TargetMachine;
+use crate::target::wasi::link_wasi_runtime;
+use llvm_sys::target_machine::LLVMCodeGenFileType::{LLVMAssemblyFile, LLVMObjectFile};
 
 type RispCallback = fn(&RispEnv, &[RispExp], Option<*mut LLVMValue>) -> Result<RispExp, RispErr>;
-// (ref, loaded)
-type LLVMValueWrapper = (*mut LLVMValue, bool);
 
 // three kinds of values
 #[derive(Clone)]
 pub enum RispExp {
     Null,
     Symbol(String),
-    Number(f64, LLVMValueWrapper),
+    Int(i64),
+    Float(f64),
+    Number(*mut LLVMValue),   // compiled value
+    Variable(*mut LLVMValue), // stack slot or global holding a binding
     List(Vec<RispExp>),
-    Func(String, RispCallback), // bam
+    Func(String, RispCallback),            // bam
+    Lambda(String, usize, *mut LLVMValue), // name, arity, compiled function
 }
 
 #[derive(Debug)]
 pub enum RispErr {
     Reason(String),
+    // the LLVM verifier rejected the generated module
+    Verify(String),
 }
 
-pub fn function_type_var_arg(ret_type: *mut LLVMType, args: &mut [*mut LLVMType]) -> *mut LLVMType {
-    unsafe { LLVMFunctionType(ret_type, args.as_mut_ptr(), 0, 1) }
+impl fmt::Display for RispErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RispErr::Reason(e) => write!(f, "{}", e),
+            RispErr::Verify(e) => write!(f, "invalid IR: {}", e),
+        }
+    }
 }
 
-pub fn create_printf(module: *mut LLVMModule) -> *mut LLVMValue {
-    let mut args_type_list = vec![pointer_type()];
-    let printf_type = function_type_var_arg(int8_type(), &mut args_type_list);
+pub fn create_printf(context: &Context, module: &Module) -> *mut LLVMValue {
+    let mut args_type_list = vec![pointer_type(context)];
+    let printf_type = function_type_var_arg(int8_type(context), &mut args_type_list);
 
     add_function(module, printf_type, "printf")
 }
 
-pub fn create_input_fn(module: *mut LLVMModule) -> *mut LLVMValue {
-    let mut args_type_list = vec![pointer_type()];
-    let fn_type = function_type_var_arg(int32_type(), &mut args_type_list);
+pub fn create_input_fn(context: &Context, module: &Module) -> *mut LLVMValue {
+    let mut args_type_list = vec![pointer_type(context)];
+    let fn_type = function_type_var_arg(int32_type(context), &mut args_type_list);
 
     add_function(module, fn_type, "scanf")
 }
@@ -63,12 +96,16 @@ impl fmt::Display for RispExp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let str = match self {
             RispExp::Symbol(s) => s.clone(),
-            RispExp::Number(n, _) => n.to_string(),
+            RispExp::Int(n) => n.to_string(),
+            RispExp::Float(n) => n.to_string(),
+            RispExp::Number(_) => "value".to_string(),
+            RispExp::Variable(_) => "variable".to_string(),
             RispExp::List(list) => {
                 let xs: Vec<String> = list.iter().map(|x| x.to_string()).collect();
                 format!("({})", xs.join(","))
             }
             RispExp::Func(f_name, _) => format!("Function {}", f_name),
+            RispExp::Lambda(f_name, _, _) => format!("Lambda {}", f_name),
             RispExp::Null => "null".to_string(),
         };
 
@@ -84,19 +121,19 @@ fn tokenize(expr: &str) -> Vec<String> {
         .collect()
 }
 
-fn parse<'a>(env: &RispEnv, tokens: &'a [String]) -> Result<(RispExp, &'a [String]), RispErr> {
+fn parse(tokens: &[String]) -> Result<(RispExp, &[String]), RispErr> {
     let (token, rest) = tokens
         .split_first()
         .ok_or(RispErr::Reason("could not get token".to_string()))?;
 
     match &token[..] {
-        "(" => read_seq(env, rest),
+        "(" => read_seq(rest),
         ")" => Err(RispErr::Reason("unexpected `)`".to_string())),
-        _ => Ok((parse_atom(env, token), rest)),
+        _ => Ok((parse_atom(token), rest)),
     }
 }
 
-fn read_seq<'a>(env: &RispEnv, tokens: &'a [String]) -> Result<(RispExp, &'a [String]), RispErr> {
+fn read_seq(tokens: &[String]) -> Result<(RispExp, &[String]), RispErr> {
     let mut res: Vec<RispExp> = vec![];
     let mut xs = tokens;
     loop {
@@ -107,31 +144,39 @@ fn read_seq<'a>(env: &RispEnv, tokens: &'a [String]) -> Result<(RispExp, &'a [St
         if next_token == ")" {
             return Ok((RispExp::List(res), rest));
         }
-        let (exp, new_xs) = parse(env, &xs)?;
+        let (exp, new_xs) = parse(xs)?;
         res.push(exp);
         xs = new_xs;
     }
 }
 
-fn parse_atom(_env: &RispEnv, token: &str) -> RispExp {
+fn parse_atom(token: &str) -> RispExp {
+    if let Ok(value) = token.parse::<i64>() {
+        return RispExp::Int(value);
+    }
+
     let potential_float: Result<f64, ParseFloatError> = token.parse();
     match potential_float {
-        Ok(value) => RispExp::Number(value, (llvm_integer!(value as u64), false)),
+        Ok(value) => RispExp::Float(value),
         Err(_) => RispExp::Symbol(token.to_string()).clone(),
     }
 }
 
-fn get_symbol(env: &RispEnv, k: &String) -> Result<RispExp, RispErr> {
+// variables are loaded where they are read, everything else is bound to its value
+fn get_symbol(env: &RispEnv, k: &str) -> Result<RispExp, RispErr> {
     // println!("Symbol: {}", k);
-    env.data
-        .get(k)
-        .ok_or(RispErr::Reason(format!("unexpected symbol k='{}'", k)))
-        .map(|x| x.clone())
+    match env.lookup(k) {
+        Some(RispExp::Variable(slot)) => {
+            Ok(RispExp::Number(build_load(&env.llvm_builder, *slot, k)))
+        }
+        Some(exp) => Ok(exp.clone()),
+        None => Err(RispErr::Reason(format!("unexpected symbol k='{}'", k))),
+    }
 }
 
 fn eval_function(
     env: &mut RispEnv,
-    f_name: &String,
+    f_name: &str,
     func: &RispCallback,
     arg_forms: &[RispExp],
 ) -> Result<RispExp, RispErr> {
@@ -140,13 +185,17 @@ fn eval_function(
         .map(|x| eval(x, env))
         .collect::<Result<Vec<RispExp>, RispErr>>();
 
-    match &f_name[..] {
-        "+" => eval_arithmetic(env, f_name, func, args_eval),
-        "-" => eval_arithmetic(env, f_name, func, args_eval),
-        "*" => eval_arithmetic(env, f_name, func, args_eval),
-        "/" => eval_arithmetic(env, f_name, func, args_eval),
+    match f_name {
+        "+" => eval_arithmetic(env, func, args_eval),
+        "-" => eval_arithmetic(env, func, args_eval),
+        "*" => eval_arithmetic(env, func, args_eval),
+        "/" => eval_arithmetic(env, func, args_eval),
+        "=" | "<" | ">" | "<=" | ">=" => eval_arithmetic(env, func, args_eval),
+
         "printf" => eval_printf_fn(env, func, args_eval),
-        "input" => eval_input_fn(env, func),
+        "input" => eval_input_fn(env, func, int32_type(env.llvm_context), "%d"),
+        "input-float" => eval_input_fn(env, func, double_type(env.llvm_context), "%lf"),
+        "float" | "int" | "long" => eval_arithmetic(env, func, args_eval),
         _ => Err(RispErr::Reason("function not found".to_string())),
     }
 }
@@ -154,48 +203,158 @@ fn eval_function(
 fn eval(exp: &RispExp, env: &mut RispEnv) -> Result<RispExp, RispErr> {
     match exp {
         RispExp::Symbol(k) => get_symbol(env, k),
-        RispExp::Number(val, _) => Ok(eval_number(env, *val)),
+        RispExp::Int(_) | RispExp::Float(_) => Ok(eval_number(env.llvm_context, exp)),
         RispExp::List(list) => {
             let first_form = list
                 .first()
                 .ok_or(RispErr::Reason("expected a non-empty list".to_string()))?;
 
             let arg_forms = &list[1..];
+            if let RispExp::Symbol(name) = first_form {
+                if let Some(result) = eval_special_form(env, name, arg_forms) {
+                    return result;
+                }
+            }
+
             let first_eval = eval(first_form, env)?;
 
             // if the first one is function
             match first_eval {
                 RispExp::Func(f_name, func) => eval_function(env, &f_name, &func, arg_forms),
+                RispExp::Lambda(f_name, arity, function) => {
+                    let args_eval = arg_forms
+                        .iter()
+                        .map(|x| eval(x, env))
+                        .collect::<Result<Vec<RispExp>, RispErr>>();
+                    eval_call_fn(env, &f_name, arity, function, args_eval)
+                }
                 _ => Err(RispErr::Reason("first form must be a function".to_string())),
             }
         }
-        RispExp::Func(_, _) => Err(RispErr::Reason("unexpected form".to_string())),
+        RispExp::Number(_)
+        | RispExp::Variable(_)
+        | RispExp::Func(_, _)
+        | RispExp::Lambda(_, _, _) => Err(RispErr::Reason("unexpected form".to_string())),
         RispExp::Null => Ok(RispExp::Null),
     }
 }
 
-fn parse_eval(env: &mut RispEnv, exp: &str) -> Result<RispExp, RispErr> {
-    let (parsed_exp, _) = parse(env, &tokenize(exp))?;
-    let exp = eval(&parsed_exp, env)?;
+// every top-level form, in order; leftover tokens are parsed as further forms
+fn parse_program(tokens: &[String]) -> Result<Vec<RispExp>, RispErr> {
+    let mut forms: Vec<RispExp> = vec![];
+    let mut xs = tokens;
+    while !xs.is_empty() {
+        let (exp, rest) = parse(xs).map_err(|e| match e {
+            RispErr::Reason(e) => RispErr::Reason(format!("form {}: {}", forms.len() + 1, e)),
+            e => e,
+        })?;
+        forms.push(exp);
+        xs = rest;
+    }
+    Ok(forms)
+}
+
+// compiles the forms in order at the builder's position, returns the last value
+fn eval_forms(env: &mut RispEnv, forms: &[RispExp]) -> Result<RispExp, RispErr> {
+    let mut exp = RispExp::Null;
+    for form in forms.iter() {
+        exp = eval(form, env)?;
+    }
+    Ok(exp)
+}
+
+fn parse_eval(env: &mut RispEnv, program: &str) -> Result<RispExp, RispErr> {
+    let mut forms = infer_program(&parse_program(&tokenize(program))?)?;
+    if env.fold {
+        forms = fold_constants(&forms);
+    }
 
-    if let RispExp::Number(_, llvm_ref) = exp {
-        build_ret(env.llvm_builder, llvm_ref.0);
+    env.setup_main();
+    let exp = eval_forms(env, &forms)?;
+
+    // main returns the value of the last form converted to i32, or 0
+    let ret_value = convert_number(
+        env,
+        value_or_zero(env.llvm_context, &exp),
+        int32_type(env.llvm_context),
+        "",
+    );
+    build_ret(&env.llvm_builder, ret_value);
+    if env.verify {
+        validate_module(&env.llvm_module)?;
     }
 
     Ok(exp)
 }
 
-fn main() -> Result<(), RispErr> {
-    let env = &mut default_env();
-    let _test1 = "(printf (- (input) 29))";
-    let _test2 = r#"(printf (- (input) (+ (input) 29)))"#;
-    let _test3 = r#"(printf (- 92 (input)))"#;
+fn main() {
+    if let Err(e) = compile() {
+        eprintln!("error: {}", e);
+        std::process::exit(1)
+    }
+}
 
-    if let Err(error) = parse_eval(env, _test2) {
-        println!("{:?}", error);
+// runs the subcommand, or compiles the program as the options ask
+fn compile() -> Result<(), RispErr> {
+    let matches = build_cli().get_matches();
+    let options = match parse_command(&matches)? {
+        Command::Compile(options) => options,
+        Command::Interpret(input) => {
+            let code = interpret(&read_source(input.as_deref())?)?;
+            std::process::exit(code)
+        }
+        Command::Run(input) => {
+            let code = run(&read_source(input.as_deref())?)?;
+            std::process::exit(code)
+        }
+        Command::Repl => return repl(),
     };
+    let source = read_source(options.input.as_deref())?;
+
+    let context = Context::new();
+    let env = &mut default_env(&context);
+    env.target = options.target;
+    env.fold = options.fold;
+    env.verify = options.verify;
+    parse_eval(env, &source)?;
+    if options.target == Target::Wasi {
+        link_wasi_runtime(&context, &env.llvm_module)?;
+    }
+    for path in options.link.iter() {
+        env.link_bitcode(path)?;
+    }
+    // reading bitcode does not verify it
+    if env.verify && !options.link.is_empty() {
+        validate_module(&env.llvm_module)?;
+    }
+    if options.opt_level > 0 {
+        let (before, after) = optimize_module(&env.llvm_module, options.opt_level);
+        eprintln!(
+            "-O{} {} -> {} instructions",
+            options.opt_level, before, after
+        );
+    }
 
-    env.emit_file("output.ll");
-
-    Ok(())
+    let machine = || TargetMachine::new(options.target);
+    match options.emit {
+        // the host's IR stays target independent
+        Emit::LlvmIr if options.target == Target::Host => env.emit_file(&options.output),
+        Emit::LlvmIr => {
+            machine()?.retarget(&env.llvm_module);
+            env.emit_file(&options.output)
+        }
+        Emit::Bitcode => {
+            if options.target != Target::Host {
+                machine()?.retarget(&env.llvm_module);
+            }
+            env.emit_bitcode(&options.output)
+        }
+        Emit::Asm => machine()?.emit_to_file(&env.llvm_module, &options.output, LLVMAssemblyFile),
+        Emit::Obj => machine()?.emit_to_file(&env.llvm_module, &options.output, LLVMObjectFile),
+        Emit::Exe if options.target == Target::Wasm32 => {
+            emit_js_glue(&env.llvm_module, &options.output)?;
+            link_executable(options.target, &env.llvm_module, &options.output)
+        }
+        Emit::Exe => link_executable(options.target, &env.llvm_module, &options.output),
+    }
 }
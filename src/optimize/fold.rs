@@ -0,0 +1,148 @@
+use crate::interp::eval::eval_pure_builtin;
+use crate::interp::value::Value;
+use crate::RispExp;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+// builtins whose result can replace the call
+const FOLDABLE: &[&str] = &["+", "-", "*", "/", "float", "int", "long"];
+// a boolean has no literal to fold into, comparisons only decide `if`s
+const COMPARISONS: &[&str] = &["=", "<", ">", "<=", ">="];
+
+// replaces calls of pure builtins on literals with their result, innermost first,
+// and `if`s with a constant test with the branch they take; runs on inferred
+// forms so the conversions inference inserted fold too
+pub fn fold_constants(forms: &[RispExp]) -> Vec<RispExp> {
+    forms
+        .iter()
+        .map(|form| fold_exp(form, &HashSet::new()))
+        .collect()
+}
+
+// `shadowed` holds the parameters of the enclosing functions, a parameter
+// named like a builtin hides it
+fn fold_exp(exp: &RispExp, shadowed: &HashSet<String>) -> RispExp {
+    let list = match exp {
+        RispExp::List(list) => list,
+        _ => return exp.clone(),
+    };
+
+    let shadowed = match &list[..] {
+        [RispExp::Symbol(form), RispExp::Symbol(_), RispExp::List(params), ..]
+            if form == "defn" =>
+        {
+            with_params(shadowed, params)
+        }
+        [RispExp::Symbol(form), RispExp::List(params), ..] if form == "lambda" => {
+            with_params(shadowed, params)
+        }
+        _ => shadowed.clone(),
+    };
+    let list: Vec<RispExp> = list.iter().map(|x| fold_exp(x, &shadowed)).collect();
+
+    let folded = match &list[..] {
+        [RispExp::Symbol(name), args @ ..]
+            if FOLDABLE.contains(&&name[..]) && !shadowed.contains(name) =>
+        {
+            fold_call(name, args)
+        }
+        [RispExp::Symbol(form), test, branches @ ..]
+            if form == "if" && matches!(branches.len(), 1 | 2) =>
+        {
+            fold_if(test, branches, &shadowed)
+        }
+        _ => None,
+    };
+    folded.unwrap_or(RispExp::List(list))
+}
+
+// the branch taken, unless it yields nothing and the `if` has to turn that into its zero
+fn fold_if(test: &RispExp, branches: &[RispExp], shadowed: &HashSet<String>) -> Option<RispExp> {
+    let taken = match (constant_test(test, shadowed)?, branches) {
+        (true, [then_form, ..]) => then_form,
+        (false, [_, else_form]) => else_form,
+        _ => return None,
+    };
+    if yields_nothing(taken) {
+        return None;
+    }
+    Some(taken.clone())
+}
+
+// the forms inference gives no type
+fn yields_nothing(exp: &RispExp) -> bool {
+    match exp {
+        RispExp::Null => true,
+        RispExp::List(list) => match &list[..] {
+            [RispExp::Symbol(form), ..] if form == "while" => true,
+            [RispExp::Symbol(name)] => name == "printf",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn constant_test(test: &RispExp, shadowed: &HashSet<String>) -> Option<bool> {
+    let value = match test {
+        RispExp::List(list) => match &list[..] {
+            [RispExp::Symbol(name), args @ ..]
+                if COMPARISONS.contains(&&name[..]) && !shadowed.contains(name) =>
+            {
+                let args = args
+                    .iter()
+                    .map(literal_value)
+                    .collect::<Option<Vec<Value>>>()?;
+                eval_pure_builtin(name, &args).ok()?
+            }
+            _ => return None,
+        },
+        _ => literal_value(test)?,
+    };
+    Some(value.is_true())
+}
+
+fn with_params(shadowed: &HashSet<String>, params: &[RispExp]) -> HashSet<String> {
+    let mut shadowed = shadowed.clone();
+    for param in params.iter() {
+        match param {
+            RispExp::Symbol(name) => shadowed.insert(name.clone()),
+            RispExp::List(typed) => match typed.first() {
+                Some(RispExp::Symbol(name)) => shadowed.insert(name.clone()),
+                _ => false,
+            },
+            _ => false,
+        };
+    }
+    shadowed
+}
+
+// None when an argument is not a literal or the call fails, e.g. dividing by zero,
+// which is then left for the program to run into
+fn fold_call(name: &str, args: &[RispExp]) -> Option<RispExp> {
+    let args = args
+        .iter()
+        .map(literal_value)
+        .collect::<Option<Vec<Value>>>()?;
+    eval_pure_builtin(name, &args).ok().and_then(value_literal)
+}
+
+fn literal_value(exp: &RispExp) -> Option<Value> {
+    match exp {
+        RispExp::Int(value) => Some(match i32::try_from(*value) {
+            Ok(value) => Value::Int(value),
+            Err(_) => Value::Long(*value),
+        }),
+        RispExp::Float(value) => Some(Value::Double(*value)),
+        _ => None,
+    }
+}
+
+// only values whose literal is typed the same way, a small i64 would become an i32
+fn value_literal(value: Value) -> Option<RispExp> {
+    match value {
+        Value::Int(value) => Some(RispExp::Int(value as i64)),
+        Value::Long(value) if i32::try_from(value).is_err() => Some(RispExp::Int(value)),
+        Value::Double(value) => Some(RispExp::Float(value)),
+        _ => None,
+    }
+}
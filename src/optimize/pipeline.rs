@@ -0,0 +1,68 @@
+use crate::ir::context::Module;
+use llvm_sys::core::{
+    LLVMCountBasicBlocks, LLVMCreatePassManager, LLVMDisposePassManager, LLVMGetFirstBasicBlock,
+    LLVMGetFirstFunction, LLVMGetFirstInstruction, LLVMGetNextBasicBlock, LLVMGetNextFunction,
+    LLVMGetNextInstruction, LLVMRunPassManager,
+};
+use llvm_sys::transforms::pass_manager_builder::{
+    LLVMPassManagerBuilderCreate, LLVMPassManagerBuilderDispose,
+    LLVMPassManagerBuilderPopulateModulePassManager, LLVMPassManagerBuilderSetOptLevel,
+    LLVMPassManagerBuilderUseInlinerWithThreshold,
+};
+use llvm_sys::transforms::util::LLVMAddPromoteMemoryToRegisterPass;
+
+pub const LEVELS: &[&str] = &["0", "1", "2", "3"];
+
+// runs the standard pipeline for `level`, starting with mem2reg since every variable lives
+// in a stack slot, and returns the instruction count before and after, inlining can
+// make the module grow
+pub fn optimize_module(module: &Module, level: u32) -> (usize, usize) {
+    let before = count_instructions(module);
+    if level == 0 {
+        return (before, before);
+    }
+
+    unsafe {
+        let passes = LLVMCreatePassManager();
+        LLVMAddPromoteMemoryToRegisterPass(passes);
+
+        let builder = LLVMPassManagerBuilderCreate();
+        LLVMPassManagerBuilderSetOptLevel(builder, level);
+        // the thresholds clang uses at -O2 and -O3
+        if level > 1 {
+            LLVMPassManagerBuilderUseInlinerWithThreshold(
+                builder,
+                if level > 2 { 250 } else { 225 },
+            );
+        }
+        LLVMPassManagerBuilderPopulateModulePassManager(builder, passes);
+        LLVMPassManagerBuilderDispose(builder);
+
+        LLVMRunPassManager(passes, module.as_raw());
+        LLVMDisposePassManager(passes);
+    }
+
+    (before, count_instructions(module))
+}
+
+fn count_instructions(module: &Module) -> usize {
+    let mut count = 0;
+    unsafe {
+        let mut function = LLVMGetFirstFunction(module.as_raw());
+        while !function.is_null() {
+            if LLVMCountBasicBlocks(function) > 0 {
+                let mut block = LLVMGetFirstBasicBlock(function);
+                while !block.is_null() {
+                    let mut instruction = LLVMGetFirstInstruction(block);
+                    while !instruction.is_null() {
+                        count += 1;
+                        instruction = LLVMGetNextInstruction(instruction);
+                    }
+                    block = LLVMGetNextBasicBlock(block);
+                }
+            }
+            function = LLVMGetNextFunction(function);
+        }
+    }
+    count
+}
@@ -0,0 +1,124 @@
+use crate::ir::context::Module;
+use crate::ir::scope::{imported_functions, value_name};
+use crate::RispErr;
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = r#"// generated by llvm-rust, implements the imports of the WebAssembly module
+let memory;
+let print = console.log;
+let read = () => prompt('input');
+let pending = '';
+
+function string(pointer) {
+  const bytes = new Uint8Array(memory.buffer, pointer);
+  return new TextDecoder().decode(bytes.subarray(0, bytes.indexOf(0)));
+}
+
+function align(pointer, size) {
+  return Math.ceil(pointer / size) * size;
+}
+
+// output is printed a line at a time
+function write(text) {
+  const lines = (pending + text).split('\n');
+  pending = lines.pop();
+  lines.forEach((line) => print(line));
+}
+"#;
+
+const PRINTF: &str = r#"
+// variadic arguments arrive as a pointer to a buffer, each one aligned to its size
+function printf(format, args) {
+  const view = new DataView(memory.buffer);
+  let text = '';
+  for (const part of string(format).split(/(%%|%l?[dif])/)) {
+    if (part === '%%') {
+      text += '%';
+    } else if (part === '%d' || part === '%i') {
+      args = align(args, 4);
+      text += view.getInt32(args, true);
+      args += 4;
+    } else if (part === '%ld' || part === '%li') {
+      args = align(args, 8);
+      text += view.getBigInt64(args, true);
+      args += 8;
+    } else if (part === '%f' || part === '%lf') {
+      args = align(args, 8);
+      text += view.getFloat64(args, true).toFixed(6);
+      args += 8;
+    } else {
+      text += part;
+    }
+  }
+  write(text);
+  return text.length;
+}
+"#;
+
+const SCANF: &str = r#"
+// every conversion reads one word of input through the pointer passed for it
+function scanf(format, args) {
+  const view = new DataView(memory.buffer);
+  let count = 0;
+  for (const part of string(format).match(/%l?[dif]/g) || []) {
+    const word = read();
+    if (word === null || word === undefined) {
+      return count || -1;
+    }
+    const pointer = view.getUint32(args, true);
+    args += 4;
+    if (part.endsWith('f')) {
+      view.setFloat64(pointer, parseFloat(word), true);
+    } else if (part.startsWith('%l')) {
+      view.setBigInt64(pointer, BigInt(word.trim()), true);
+    } else {
+      view.setInt32(pointer, parseInt(word, 10), true);
+    }
+    count += 1;
+  }
+  return count;
+}
+"#;
+
+// libc functions generated code may call, implemented in JS
+fn implementation(name: &str) -> Option<&'static str> {
+    match name {
+        "printf" => Some(PRINTF),
+        "scanf" => Some(SCANF),
+        _ => None,
+    }
+}
+
+// writes the JS module that instantiates `wasm_path`, next to it with a `.js` extension
+pub fn emit_js_glue(module: &Module, wasm_path: &str) -> Result<(), RispErr> {
+    let names = imported_functions(module)
+        .into_iter()
+        .map(value_name)
+        .collect::<Vec<String>>();
+
+    let mut js = HEADER.to_string();
+    for name in names.iter() {
+        js += implementation(name).ok_or_else(|| {
+            RispErr::Reason(format!("no JS implementation for import `{}`", name))
+        })?;
+    }
+    js += &format!(
+        r#"
+// `source` is the module's bytes, `print` receives each line and `read` returns a word of
+// input or null at the end
+export async function instantiate(source, options = {{}}) {{
+  print = options.print || print;
+  read = options.read || read;
+  const {{ instance }} = await WebAssembly.instantiate(source, {{ env: {{ {} }} }});
+  memory = instance.exports.memory;
+  return instance.exports;
+}}
+"#,
+        names.join(", ")
+    );
+
+    let path = Path::new(wasm_path).with_extension("js");
+    fs::write(&path, js)
+        .map_err(|e| RispErr::Reason(format!("cannot write `{}`: {}", path.display(), e)))
+}
@@ -0,0 +1,83 @@
+use crate::cli::Target;
+use crate::ir::context::Module;
+use crate::target::machine::TargetMachine;
+use crate::RispErr;
+use llvm_sys::target_machine::LLVMCodeGenFileType::LLVMObjectFile;
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// writes an object file next to `output` and links it with the target's linker
+pub fn link_executable(target: Target, module: &Module, output: &str) -> Result<(), RispErr> {
+    let object = create_object_file(Path::new(output))?;
+    let object_path = object.to_string_lossy();
+    if let Err(e) = TargetMachine::new(target)
+        .and_then(|machine| machine.emit_to_file(module, &object_path, LLVMObjectFile))
+    {
+        fs::remove_file(&object).ok();
+        return Err(e);
+    }
+
+    let (linker, args): (&str, &[&str]) = match target {
+        Target::Host => ("cc", &[]),
+        // `main` is called by the embedder, everything left undefined becomes an import
+        Target::Wasm32 => (
+            "wasm-ld",
+            &[
+                "--no-entry",
+                "--export=main",
+                "--strip-all",
+                "--allow-undefined",
+            ],
+        ),
+        // a command started through `_start`, every import is a WASI call
+        Target::Wasi => ("wasm-ld", &["--strip-all"]),
+    };
+    let status = Command::new(linker)
+        .args(args)
+        .arg(&object)
+        .arg("-o")
+        .arg(output)
+        .status();
+    fs::remove_file(&object).ok();
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(RispErr::Reason(format!(
+            "`{}` failed with {}",
+            linker, status
+        ))),
+        Err(e) => Err(RispErr::Reason(format!("cannot run `{}`: {}", linker, e))),
+    }
+}
+
+// claims a file name no other file has, a name taken by another run or planted
+// beforehand is skipped rather than written through
+fn create_object_file(output: &Path) -> Result<PathBuf, RispErr> {
+    let name = output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let object =
+            output.with_file_name(format!(".{}.{}-{}.o", name, std::process::id(), attempt));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&object)
+        {
+            Ok(_) => return Ok(object),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(RispErr::Reason(format!(
+                    "cannot create `{}`: {}",
+                    object.display(),
+                    e
+                )))
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::cli::Target;
+use crate::ir::context::Module;
+use crate::ir::diagnostic::take_message;
+use crate::RispErr;
+use llvm_sys::core::{LLVMDisposeMessage, LLVMSetTarget};
+use llvm_sys::target::{
+    LLVMDisposeTargetData, LLVMInitializeWebAssemblyAsmPrinter, LLVMInitializeWebAssemblyTarget,
+    LLVMInitializeWebAssemblyTargetInfo, LLVMInitializeWebAssemblyTargetMC,
+    LLVMSetModuleDataLayout, LLVM_InitializeNativeAsmPrinter, LLVM_InitializeNativeTarget,
+};
+use llvm_sys::target_machine::{
+    LLVMCodeGenFileType, LLVMCodeGenOptLevel, LLVMCodeModel, LLVMCreateTargetDataLayout,
+    LLVMCreateTargetMachine, LLVMDisposeTargetMachine, LLVMGetDefaultTargetTriple,
+    LLVMGetHostCPUFeatures, LLVMGetHostCPUName, LLVMGetTargetFromTriple,
+    LLVMGetTargetMachineTriple, LLVMRelocMode, LLVMTargetMachineEmitToFile, LLVMTargetMachineRef,
+};
+use std::ptr::null_mut;
+
+pub fn initialize_native_target() -> Result<(), RispErr> {
+    let failed =
+        unsafe { LLVM_InitializeNativeTarget() != 0 || LLVM_InitializeNativeAsmPrinter() != 0 };
+    if failed {
+        return Err(RispErr::Reason(
+            "cannot initialize the native target".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn initialize_wasm_target() {
+    unsafe {
+        LLVMInitializeWebAssemblyTargetInfo();
+        LLVMInitializeWebAssemblyTarget();
+        LLVMInitializeWebAssemblyTargetMC();
+        LLVMInitializeWebAssemblyAsmPrinter();
+    }
+}
+
+// generates machine code for one target
+pub struct TargetMachine {
+    machine: LLVMTargetMachineRef,
+}
+
+impl TargetMachine {
+    pub fn new(target: Target) -> Result<Self, RispErr> {
+        match target {
+            // position independent so that `cc` can link it
+            Target::Host => {
+                initialize_native_target()?;
+                let triple = take_message(unsafe { LLVMGetDefaultTargetTriple() });
+                let cpu = take_message(unsafe { LLVMGetHostCPUName() });
+                let features = take_message(unsafe { LLVMGetHostCPUFeatures() });
+                Self::create(&triple, &cpu, &features, LLVMRelocMode::LLVMRelocPIC)
+            }
+            Target::Wasm32 => {
+                initialize_wasm_target();
+                Self::create(
+                    "wasm32-unknown-unknown",
+                    "generic",
+                    "",
+                    LLVMRelocMode::LLVMRelocDefault,
+                )
+            }
+            Target::Wasi => {
+                initialize_wasm_target();
+                Self::create(
+                    "wasm32-wasi",
+                    "generic",
+                    "",
+                    LLVMRelocMode::LLVMRelocDefault,
+                )
+            }
+        }
+    }
+
+    fn create(
+        triple: &str,
+        cpu: &str,
+        features: &str,
+        reloc: LLVMRelocMode,
+    ) -> Result<Self, RispErr> {
+        let triple = c_string!(triple);
+        let mut target = null_mut();
+        let mut error: *mut i8 = null_mut();
+        if unsafe { LLVMGetTargetFromTriple(triple.as_ptr(), &mut target, &mut error) } != 0 {
+            return Err(RispErr::Reason(take_message(error)));
+        }
+
+        let machine = unsafe {
+            LLVMCreateTargetMachine(
+                target,
+                triple.as_ptr(),
+                c_string!(cpu).as_ptr(),
+                c_string!(features).as_ptr(),
+                LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+                reloc,
+                LLVMCodeModel::LLVMCodeModelDefault,
+            )
+        };
+        if machine.is_null() {
+            return Err(RispErr::Reason(format!(
+                "cannot create a target machine for `{}`",
+                triple.to_string_lossy()
+            )));
+        }
+        Ok(TargetMachine { machine })
+    }
+
+    // sets the module's triple and data layout to this machine's
+    pub fn retarget(&self, module: &Module) {
+        unsafe {
+            let triple = LLVMGetTargetMachineTriple(self.machine);
+            LLVMSetTarget(module.as_raw(), triple);
+            LLVMDisposeMessage(triple);
+            let layout = LLVMCreateTargetDataLayout(self.machine);
+            LLVMSetModuleDataLayout(module.as_raw(), layout);
+            LLVMDisposeTargetData(layout);
+        }
+    }
+
+    // writes the module as assembly or an object file for this machine
+    pub fn emit_to_file(
+        &self,
+        module: &Module,
+        path: &str,
+        file_type: LLVMCodeGenFileType,
+    ) -> Result<(), RispErr> {
+        self.retarget(module);
+
+        let mut error: *mut i8 = null_mut();
+        let path = c_string!(path);
+        let failed = unsafe {
+            LLVMTargetMachineEmitToFile(
+                self.machine,
+                module.as_raw(),
+                path.as_ptr() as *mut _,
+                file_type,
+                &mut error,
+            )
+        };
+
+        if failed != 0 {
+            return Err(RispErr::Reason(take_message(error)));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TargetMachine {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeTargetMachine(self.machine) }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod glue;
+pub mod link;
+pub mod machine;
+pub mod wasi;
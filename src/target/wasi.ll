@@ -0,0 +1,267 @@
+; `printf` and `input` for wasm32-wasi, linked into the program after code generation
+
+%iovec = type { i8*, i32 }
+
+declare i32 @fd_write(i32, %iovec*, i32, i32*) #0
+declare i32 @fd_read(i32, %iovec*, i32, i32*) #1
+declare void @proc_exit(i32) #2
+declare i32 @main()
+declare double @llvm.fabs.f64(double)
+declare double @llvm.nearbyint.f64(double)
+
+@result = private constant [8 x i8] c"Result: "
+@newline = private constant [1 x i8] c"\0A"
+@minus = private constant [1 x i8] c"-"
+@point = private constant [1 x i8] c"."
+
+; WASI commands start here and exit with what `main` returns
+define void @_start() {
+entry:
+  %code = call i32 @main()
+  call void @proc_exit(i32 %code)
+  unreachable
+}
+
+define private void @write(i8* %bytes, i32 %length) {
+entry:
+  %iov = alloca %iovec
+  %written = alloca i32
+  %base = getelementptr %iovec, %iovec* %iov, i32 0, i32 0
+  store i8* %bytes, i8** %base
+  %size = getelementptr %iovec, %iovec* %iov, i32 0, i32 1
+  store i32 %length, i32* %size
+  %errno = call i32 @fd_write(i32 1, %iovec* %iov, i32 1, i32* %written)
+  ret void
+}
+
+; `value` in decimal, padded with zeros to at least `width` digits
+define private void @write_digits(i64 %value, i32 %width) {
+entry:
+  %buffer = alloca [20 x i8]
+  br label %loop
+
+loop:
+  %rest = phi i64 [ %value, %entry ], [ %next, %loop ]
+  %end = phi i32 [ 20, %entry ], [ %index, %loop ]
+  %index = sub i32 %end, 1
+  %digit = urem i64 %rest, 10
+  %digit8 = trunc i64 %digit to i8
+  %char = add i8 %digit8, 48
+  %slot = getelementptr [20 x i8], [20 x i8]* %buffer, i32 0, i32 %index
+  store i8 %char, i8* %slot
+  %next = udiv i64 %rest, 10
+  %written = sub i32 20, %index
+  %more = icmp ne i64 %next, 0
+  %short = icmp slt i32 %written, %width
+  %again = or i1 %more, %short
+  br i1 %again, label %loop, label %done
+
+done:
+  call void @write(i8* %slot, i32 %written)
+  ret void
+}
+
+define private void @write_sign(i1 %negative) {
+entry:
+  br i1 %negative, label %sign, label %done
+
+sign:
+  call void @write(i8* getelementptr ([1 x i8], [1 x i8]* @minus, i32 0, i32 0), i32 1)
+  br label %done
+
+done:
+  ret void
+}
+
+define private void @write_result() {
+entry:
+  call void @write(i8* getelementptr ([8 x i8], [8 x i8]* @result, i32 0, i32 0), i32 8)
+  ret void
+}
+
+define private void @write_newline() {
+entry:
+  call void @write(i8* getelementptr ([1 x i8], [1 x i8]* @newline, i32 0, i32 0), i32 1)
+  ret void
+}
+
+; "Result: %ld\n"
+define void @risp_print_i64(i64 %value) {
+entry:
+  call void @write_result()
+  %negative = icmp slt i64 %value, 0
+  call void @write_sign(i1 %negative)
+  %negated = sub i64 0, %value
+  %magnitude = select i1 %negative, i64 %negated, i64 %value
+  call void @write_digits(i64 %magnitude, i32 1)
+  call void @write_newline()
+  ret void
+}
+
+; "Result: %d\n"
+define void @risp_print_i32(i32 %value) {
+entry:
+  %wide = sext i32 %value to i64
+  call void @risp_print_i64(i64 %wide)
+  ret void
+}
+
+; "Result: %f\n", six decimals rounded to even, for magnitudes below 2^64 / 10^6
+define void @risp_print_f64(double %value) {
+entry:
+  call void @write_result()
+  %negative = fcmp olt double %value, 0.0
+  call void @write_sign(i1 %negative)
+  %magnitude = call double @llvm.fabs.f64(double %value)
+  %scaled = fmul double %magnitude, 1.0e6
+  %rounded = call double @llvm.nearbyint.f64(double %scaled)
+  %micros = fptoui double %rounded to i64
+  %whole = udiv i64 %micros, 1000000
+  %fraction = urem i64 %micros, 1000000
+  call void @write_digits(i64 %whole, i32 1)
+  call void @write(i8* getelementptr ([1 x i8], [1 x i8]* @point, i32 0, i32 0), i32 1)
+  call void @write_digits(i64 %fraction, i32 6)
+  call void @write_newline()
+  ret void
+}
+
+; the next byte of stdin, -1 at its end
+define private i32 @read_byte() {
+entry:
+  %byte = alloca i8
+  %iov = alloca %iovec
+  %count = alloca i32
+  %base = getelementptr %iovec, %iovec* %iov, i32 0, i32 0
+  store i8* %byte, i8** %base
+  %size = getelementptr %iovec, %iovec* %iov, i32 0, i32 1
+  store i32 1, i32* %size
+  %errno = call i32 @fd_read(i32 0, %iovec* %iov, i32 1, i32* %count)
+  %read = load i32, i32* %count
+  %success = icmp eq i32 %errno, 0
+  %nonempty = icmp eq i32 %read, 1
+  %ok = and i1 %success, %nonempty
+  %char = load i8, i8* %byte
+  %wide = zext i8 %char to i32
+  %result = select i1 %ok, i32 %wide, i32 -1
+  ret i32 %result
+}
+
+; the first byte after any whitespace
+define private i32 @skip_space() {
+entry:
+  br label %loop
+
+loop:
+  %char = call i32 @read_byte()
+  %space = icmp eq i32 %char, 32
+  %tab = icmp eq i32 %char, 9
+  %newline = icmp eq i32 %char, 10
+  %return = icmp eq i32 %char, 13
+  %blank = or i1 %space, %tab
+  %line = or i1 %newline, %return
+  %skip = or i1 %blank, %line
+  br i1 %skip, label %loop, label %done
+
+done:
+  ret i32 %char
+}
+
+; accumulates the digits starting at `char` into `value`, multiplying `scale` by ten for
+; each one, and returns the byte that ended them
+define private i32 @read_digits(i32 %first, i64* %value, double* %scale) {
+entry:
+  br label %loop
+
+loop:
+  %char = phi i32 [ %first, %entry ], [ %next, %digit ]
+  %above = icmp sge i32 %char, 48
+  %below = icmp sle i32 %char, 57
+  %is_digit = and i1 %above, %below
+  br i1 %is_digit, label %digit, label %done
+
+digit:
+  %old = load i64, i64* %value
+  %shifted = mul i64 %old, 10
+  %char64 = zext i32 %char to i64
+  %units = sub i64 %char64, 48
+  %new = add i64 %shifted, %units
+  store i64 %new, i64* %value
+  %old_scale = load double, double* %scale
+  %new_scale = fmul double %old_scale, 10.0
+  store double %new_scale, double* %scale
+  %next = call i32 @read_byte()
+  br label %loop
+
+done:
+  ret i32 %char
+}
+
+; reads a word like scanf's "%d", 0 at the end of input
+define i32 @risp_read_i32() {
+entry:
+  %value = alloca i64
+  store i64 0, i64* %value
+  %scale = alloca double
+  %first = call i32 @skip_space()
+  %negative = icmp eq i32 %first, 45
+  br i1 %negative, label %sign, label %digits
+
+sign:
+  %second = call i32 @read_byte()
+  br label %digits
+
+digits:
+  %char = phi i32 [ %first, %entry ], [ %second, %sign ]
+  %stop = call i32 @read_digits(i32 %char, i64* %value, double* %scale)
+  %magnitude = load i64, i64* %value
+  %negated = sub i64 0, %magnitude
+  %signed = select i1 %negative, i64 %negated, i64 %magnitude
+  %result = trunc i64 %signed to i32
+  ret i32 %result
+}
+
+; reads a word like scanf's "%lf", without exponents, 0 at the end of input
+define double @risp_read_f64() {
+entry:
+  %whole = alloca i64
+  store i64 0, i64* %whole
+  %fraction = alloca i64
+  store i64 0, i64* %fraction
+  %scale = alloca double
+  store double 1.0, double* %scale
+  %first = call i32 @skip_space()
+  %negative = icmp eq i32 %first, 45
+  br i1 %negative, label %sign, label %digits
+
+sign:
+  %second = call i32 @read_byte()
+  br label %digits
+
+digits:
+  %char = phi i32 [ %first, %entry ], [ %second, %sign ]
+  %stop = call i32 @read_digits(i32 %char, i64* %whole, double* %scale)
+  store double 1.0, double* %scale
+  %point = icmp eq i32 %stop, 46
+  br i1 %point, label %decimals, label %done
+
+decimals:
+  %after = call i32 @read_byte()
+  %end = call i32 @read_digits(i32 %after, i64* %fraction, double* %scale)
+  br label %done
+
+done:
+  %units = load i64, i64* %whole
+  %units_real = uitofp i64 %units to double
+  %part = load i64, i64* %fraction
+  %part_real = uitofp i64 %part to double
+  %divisor = load double, double* %scale
+  %decimal = fdiv double %part_real, %divisor
+  %magnitude = fadd double %units_real, %decimal
+  %negated = fneg double %magnitude
+  %result = select i1 %negative, double %negated, double %magnitude
+  ret double %result
+}
+
+attributes #0 = { "wasm-import-module"="wasi_snapshot_preview1" "wasm-import-name"="fd_write" }
+attributes #1 = { "wasm-import-module"="wasi_snapshot_preview1" "wasm-import-name"="fd_read" }
+attributes #2 = { noreturn "wasm-import-module"="wasi_snapshot_preview1" "wasm-import-name"="proc_exit" }
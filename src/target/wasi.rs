@@ -0,0 +1,40 @@
+use crate::ir::context::{Context, Module};
+use crate::ir::diagnostic::{capture_errors, take_message};
+use crate::RispErr;
+use llvm_sys::core::LLVMCreateMemoryBufferWithMemoryRangeCopy;
+use llvm_sys::ir_reader::LLVMParseIRInContext;
+use llvm_sys::linker::LLVMLinkModules2;
+use std::ptr::null_mut;
+
+// defines `_start` and the `risp_print_*`/`risp_read_*` functions code generation calls
+// for wasm32-wasi, on top of `fd_write`, `fd_read` and `proc_exit`
+const RUNTIME: &str = include_str!("wasi.ll");
+
+pub fn link_wasi_runtime(context: &Context, module: &Module) -> Result<(), RispErr> {
+    let mut runtime = null_mut();
+    let mut error: *mut i8 = null_mut();
+    let failed = unsafe {
+        let buffer = LLVMCreateMemoryBufferWithMemoryRangeCopy(
+            RUNTIME.as_ptr() as *const _,
+            RUNTIME.len(),
+            c_str!("wasi.ll"),
+        );
+        // the parser takes ownership of the buffer
+        LLVMParseIRInContext(context.as_raw(), buffer, &mut runtime, &mut error)
+    };
+    if failed != 0 {
+        return Err(RispErr::Reason(take_message(error)));
+    }
+
+    // consumes `runtime`
+    let (failed, errors) = capture_errors(context, || unsafe {
+        LLVMLinkModules2(module.as_raw(), runtime)
+    });
+    if failed != 0 {
+        return Err(RispErr::Reason(format!(
+            "cannot link the WASI runtime: {}",
+            errors.join(", ")
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+// shared by the integration tests, each of which uses only part of it
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// compiles `source` to a native executable with `--emit=exe`, which links with `cc`
+pub fn build_native(source: &str, name: &str) -> PathBuf {
+    require_tool("cc");
+    emit(source, name, &["--emit=exe"], "out")
+}
+
+// fails the test when `tool` is missing, tests that need an uncommon tool are
+// `#[ignore]`d instead so that only `--ignored` runs them
+pub fn require_tool(tool: &str) {
+    let found = Command::new(tool).arg("--version").output().is_ok();
+    assert!(found, "`{}` is not installed", tool);
+}
+
+// compiles `source` with the given flags and returns the file it wrote
+pub fn emit(source: &str, name: &str, args: &[&str], file_name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("llvm-rust-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let output = dir.join(file_name);
+
+    let mut compiler = Command::new(env!("CARGO_BIN_EXE_llvm-rust"))
+        .args(args)
+        .arg("-o")
+        .arg(&output)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    compiler
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    assert!(compiler.wait().unwrap().success(), "compilation failed");
+
+    output
+}
+
+pub fn run(command: &mut Command) {
+    let status = command.status().unwrap();
+    assert!(status.success(), "{:?} failed", command);
+}
+
+pub fn stdout_of(exe: &Path) -> String {
+    let output = Command::new(exe).output().unwrap();
+    assert!(output.status.success(), "{} crashed", exe.display());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// stdout and exit code of `command` when `stdin` is written to it
+pub fn output_with_input(command: &mut Command, stdin: &str) -> (String, i32) {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.code().unwrap_or(-1),
+    )
+}
+
+// runs `source` with the interpreter, the program is passed as a file so stdin stays free
+pub fn interpret(source: &str, name: &str, stdin: &str) -> (String, i32) {
+    run_with("interpret", source, name, stdin)
+}
+
+// runs `source` compiled in-process by the JIT
+pub fn jit_run(source: &str, name: &str, stdin: &str) -> (String, i32) {
+    run_with("run", source, name, stdin)
+}
+
+fn run_with(subcommand: &str, source: &str, name: &str, stdin: &str) -> (String, i32) {
+    let dir = env::temp_dir().join(format!(
+        "llvm-rust-{}-{}-{}",
+        name,
+        subcommand,
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("program.lisp");
+    fs::write(&path, source).unwrap();
+
+    output_with_input(
+        Command::new(env!("CARGO_BIN_EXE_llvm-rust"))
+            .arg(subcommand)
+            .arg(&path),
+        stdin,
+    )
+}
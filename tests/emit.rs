@@ -0,0 +1,267 @@
+mod common;
+
+use common::{emit, jit_run, output_with_input, require_tool, run, stdout_of};
+use std::fs;
+use std::process::{Command, Stdio};
+
+const SOURCE: &str = r#"
+    (defn twice (x) (* x 2))
+    (printf (twice 21))
+    (printf (/ 1 4.0))
+    0
+"#;
+
+#[test]
+fn object_files_link_with_cc() {
+    require_tool("cc");
+    let obj = emit(SOURCE, "emit-obj", &["--emit=obj"], "out.o");
+    let exe = obj.with_file_name("out");
+    run(Command::new("cc").arg(&obj).arg("-o").arg(&exe));
+    assert_eq!(stdout_of(&exe), "Result: 42\nResult: 0.250000\n");
+}
+
+#[test]
+fn assembly_defines_every_function() {
+    let asm = emit(SOURCE, "emit-asm", &["--emit=asm"], "out.s");
+    let text = fs::read_to_string(asm).unwrap();
+    assert!(text.contains("main:"));
+    assert!(text.contains("twice:"));
+}
+
+#[test]
+fn wasm_objects_are_webassembly_modules() {
+    let args = ["--target", "wasm32-unknown-unknown", "--emit=obj"];
+    let obj = emit(SOURCE, "emit-wasm-obj", &args, "out.o");
+    assert!(fs::read(obj).unwrap().starts_with(b"\0asm"));
+}
+
+#[test]
+#[ignore = "needs wasm-ld"]
+fn wasm_executables_export_main() {
+    require_tool("wasm-ld");
+    let args = ["--target", "wasm32-unknown-unknown", "--emit=exe"];
+    let wasm = fs::read(emit(SOURCE, "emit-wasm", &args, "out.wasm")).unwrap();
+    assert!(wasm.starts_with(b"\0asm"));
+    assert!(wasm.windows(4).any(|name| name == b"main"));
+}
+
+#[test]
+#[ignore = "needs wasm-ld and node"]
+fn wasm_glue_prints_and_reads_input() {
+    require_tool("wasm-ld");
+    require_tool("node");
+    let source = "(define n (input)) (printf (* n 2)) (printf (/ n 8.0)) (+ n 1)";
+    let args = ["--target", "wasm32-unknown-unknown", "--emit=exe"];
+    let wasm = emit(source, "emit-wasm-glue", &args, "out.wasm");
+
+    // the glue is an ES module
+    fs::write(wasm.with_file_name("package.json"), r#"{"type": "module"}"#).unwrap();
+    let runner = wasm.with_file_name("run.js");
+    fs::write(
+        &runner,
+        r#"
+        import { readFileSync } from 'fs';
+        import { instantiate } from './out.js';
+        const words = ['20'];
+        const bytes = readFileSync(new URL('./out.wasm', import.meta.url));
+        const exports = await instantiate(bytes, { read: () => words.shift() ?? null });
+        console.log(exports.main());
+        "#,
+    )
+    .unwrap();
+
+    let output = Command::new("node").arg(&runner).output().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Result: 40\nResult: 2.500000\n21\n"
+    );
+}
+
+#[test]
+fn wasi_objects_import_only_wasi() {
+    let args = ["--target", "wasm32-wasi", "--emit=obj"];
+    let source = "(define n (input-float)) (printf (* n 2)) (printf 3000000000) 0";
+    let obj = fs::read(emit(source, "emit-wasi-obj", &args, "out.o")).unwrap();
+    let contains = |name: &[u8]| obj.windows(name.len()).any(|bytes| bytes == name);
+    assert!(contains(b"wasi_snapshot_preview1"));
+    assert!(contains(b"fd_write"));
+    assert!(!contains(b"printf"));
+    assert!(!contains(b"scanf"));
+}
+
+#[test]
+#[ignore = "needs wasm-ld and node"]
+fn wasi_programs_run_under_node() {
+    require_tool("wasm-ld");
+    require_tool("node");
+    let source = "(define n (input)) (define x (input-float)) \
+                  (printf (* n 2)) (printf (/ x 8)) (printf (- 0 3000000000)) (+ n 1)";
+    let args = ["--target", "wasm32-wasi", "--emit=exe"];
+    let wasm = emit(source, "emit-wasi", &args, "out.wasm");
+
+    let runner = wasm.with_file_name("run.mjs");
+    fs::write(
+        &runner,
+        r#"
+        import { readFileSync } from 'fs';
+        import { WASI } from 'wasi';
+        const wasi = new WASI({ version: 'preview1', returnOnExit: true });
+        const bytes = readFileSync(new URL('./out.wasm', import.meta.url));
+        const { instance } = await WebAssembly.instantiate(bytes, wasi.getImportObject());
+        process.exitCode = wasi.start(instance);
+        "#,
+    )
+    .unwrap();
+
+    let (stdout, code) = output_with_input(
+        Command::new("node").arg("--no-warnings").arg(&runner),
+        "20 -2.5\n",
+    );
+    assert_eq!(
+        stdout,
+        "Result: 40\nResult: -0.312500\nResult: -3000000000\n"
+    );
+    assert_eq!(code, 21);
+}
+
+#[test]
+fn bitcode_loads_back_for_linking() {
+    let bc = emit(SOURCE, "emit-bc", &["--emit=bc"], "out.bc");
+    assert!(fs::read(&bc).unwrap().starts_with(b"BC\xC0\xDE"));
+
+    // the module parses, but both programs define `main`
+    let mut compiler = Command::new(env!("CARGO_BIN_EXE_llvm-rust"));
+    compiler
+        .arg("--link")
+        .arg(&bc)
+        .arg("-o")
+        .arg(bc.with_extension("ll"));
+    let (_, code) = output_with_input(&mut compiler, "0");
+    assert_ne!(code, 0);
+
+    require_tool("llvm-as");
+    let helper = bc.with_file_name("helper.ll");
+    fs::write(&helper, "define i32 @helper(i32 %x) {\n  ret i32 %x\n}\n").unwrap();
+    run(Command::new("llvm-as").arg(&helper));
+    let linked = emit(
+        SOURCE,
+        "emit-bc-link",
+        &["--link", helper.with_extension("bc").to_str().unwrap()],
+        "out.ll",
+    );
+    let ir = fs::read_to_string(linked).unwrap();
+    assert!(ir.contains("define i32 @helper(i32 %x)"));
+    assert!(ir.contains("define i32 @twice(i32"));
+}
+
+#[test]
+fn optimization_levels_keep_behaviour_and_drop_stack_slots() {
+    let source = r#"
+        (defn count (n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+        (define total 0)
+        (define i 0)
+        (while (< i 10) (set! total (+ total (count i 0))) (set! i (+ i 1)))
+        (defn twice (x) (* x 2))
+        (printf total)
+        (printf (twice 2.25))
+        total
+    "#;
+
+    let unoptimized = emit(source, "opt-0", &["-O0"], "out.ll");
+    let optimized = emit(source, "opt-2", &["-O2"], "out.ll");
+    assert!(fs::read_to_string(unoptimized).unwrap().contains("alloca"));
+    assert!(!fs::read_to_string(optimized).unwrap().contains("alloca"));
+
+    require_tool("cc");
+    let outputs = ["-O0", "-O1", "-O2", "-O3"]
+        .iter()
+        .map(|level| {
+            let exe = emit(
+                source,
+                &format!("opt-exe{}", level),
+                &["--emit=exe", level],
+                "out",
+            );
+            output_with_input(&mut Command::new(exe), "")
+        })
+        .collect::<Vec<(String, i32)>>();
+    assert_eq!(
+        outputs[0],
+        ("Result: 45\nResult: 4.500000\n".to_string(), 45)
+    );
+    assert!(outputs.iter().all(|output| *output == outputs[0]));
+}
+
+#[test]
+fn only_variables_get_stack_slots() {
+    let source = r#"
+        (defn twice (x) (* x 2))
+        (printf (+ 1 (twice 3) 4.0))
+    "#;
+    let ir = fs::read_to_string(emit(source, "ssa", &[], "out.ll")).unwrap();
+    // the parameter is the only thing that can be reassigned
+    assert_eq!(ir.matches("alloca").count(), 1);
+    assert!(ir.contains("mul i32 %x1, 2"));
+}
+
+#[test]
+fn constant_tests_drop_the_branch_not_taken() {
+    let source = r#"
+        (defn f (x) (if (< 1 (+ 1 1)) (+ x 1) (* x 2)))
+        (f 3)
+    "#;
+    let folded = fs::read_to_string(emit(source, "fold", &[], "out.ll")).unwrap();
+    assert!(!folded.contains("br i1"));
+    assert!(!folded.contains("mul"));
+
+    let unfolded = fs::read_to_string(emit(source, "no-fold", &["--no-fold"], "out.ll")).unwrap();
+    assert!(unfolded.contains("br i1 true"));
+    assert!(unfolded.contains("mul"));
+}
+
+#[test]
+fn verification_does_not_change_the_module() {
+    let verified = fs::read_to_string(emit(SOURCE, "verify", &[], "out.ll")).unwrap();
+    let unverified = emit(SOURCE, "no-verify", &["--no-verify"], "out.ll");
+    assert_eq!(verified, fs::read_to_string(unverified).unwrap());
+}
+
+#[test]
+fn constant_tests_keep_zeros() {
+    // the taken branch has no value, the `if` still yields zero
+    let source = r#"
+        (printf (if 1 (while 0)))
+        0
+    "#;
+    let (stdout, code) = jit_run(source, "fold-zeros", "");
+    assert_eq!(stdout, "Result: 0\n");
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn invalid_modules_fail_with_the_verifier_message() {
+    require_tool("llvm-as");
+    let ll = emit(SOURCE, "verify-invalid", &[], "out.ll");
+    let invalid = ll.with_file_name("invalid.ll");
+    fs::write(
+        &invalid,
+        "define i32 @invalid(i32 %x) {\nentry:\n  br label %next\n\nnext:\n  ret i32 %y\n\n\
+         late:\n  %y = add i32 %x, 1\n  br label %next\n}\n",
+    )
+    .unwrap();
+    run(Command::new("llvm-as")
+        .arg("--disable-verify")
+        .arg(&invalid));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_llvm-rust"))
+        .arg("--link")
+        .arg(invalid.with_extension("bc"))
+        .arg("-o")
+        .arg(ll.with_file_name("linked.ll"))
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("error: invalid IR: Instruction does not dominate all uses!"));
+}
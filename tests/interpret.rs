@@ -0,0 +1,121 @@
+mod common;
+
+use common::{build_native, interpret, jit_run, output_with_input};
+use std::process::Command;
+
+// the interpreter is the reference: a compiled program has to print the same and exit the same
+fn assert_matches_compiled(source: &str, name: &str, stdin: &str) -> (String, i32) {
+    let interpreted = interpret(source, name, stdin);
+    assert_eq!(interpreted, jit_run(source, name, stdin));
+
+    let compiled = output_with_input(&mut Command::new(build_native(source, name)), stdin);
+    assert_eq!(interpreted, compiled);
+    interpreted
+}
+
+#[test]
+fn arithmetic_and_conversions() {
+    let source = r#"
+        (printf (+ 1 2 3))
+        (printf (- 10 4 3))
+        (printf (- 7))
+        (printf (* 6 7))
+        (printf (/ 7 2))
+        (printf (/ 7 2.0))
+        (printf (+ 1 (< 2 3)))
+        (printf (int 2.9))
+        (printf (* 3000000000 2))
+        (- 50 8)
+    "#;
+
+    let (stdout, code) = assert_matches_compiled(source, "interpret-arithmetic", "");
+    assert_eq!(
+        stdout,
+        "Result: 6\nResult: 3\nResult: -7\nResult: 42\nResult: 3\nResult: 3.500000\n\
+         Result: 2\nResult: 2\nResult: 6000000000\n"
+    );
+    assert_eq!(code, 42);
+}
+
+#[test]
+fn variables_loops_and_input() {
+    let source = r#"
+        (define n (input))
+        (define acc 0)
+        (while (> n 0) (set! acc (+ acc n)) (set! n (- n 1)))
+        (printf acc)
+        (printf (cond ((< acc 10) 1) (else (/ acc 2.0))))
+        (printf (input-float))
+        0
+    "#;
+
+    let (stdout, _) = assert_matches_compiled(source, "interpret-loops", "10 2.25\n");
+    assert_eq!(stdout, "Result: 55\nResult: 27.500000\nResult: 2.250000\n");
+}
+
+#[test]
+fn functions_with_inferred_signatures() {
+    let source = r#"
+        (defn fact (n) (if (< n 2) 1 (* n (fact (- n 1)))))
+        (defn half (x) (/ x 2))
+        (defn count (n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+        (define twice (lambda (x) (+ x x)))
+        (printf (fact (long 20)))
+        (printf (half 5.0))
+        (printf (count 100000 0))
+        (printf (twice 21))
+        (fact 5)
+    "#;
+
+    let (stdout, code) = assert_matches_compiled(source, "interpret-functions", "");
+    assert_eq!(
+        stdout,
+        "Result: 2432902008176640000\nResult: 2.500000\nResult: 100000\nResult: 42\n"
+    );
+    assert_eq!(code, 120);
+}
+
+#[test]
+fn missing_branches() {
+    let source = r#"
+        (printf (if 0 1))
+        (printf (if 0 2.5))
+        (printf (cond ((< 2 1) (long 3))))
+        0
+    "#;
+
+    let (stdout, _) = assert_matches_compiled(source, "interpret-missing-branches", "");
+    assert_eq!(stdout, "Result: 0\nResult: 0.000000\nResult: 0\n");
+}
+
+#[test]
+fn deep_recursion_is_an_error() {
+    let source = r#"
+        (defn depth (n) (if (= n 0) 0 (+ 1 (depth (- n 1)))))
+        (printf (depth 5000))
+        (printf (depth 100000))
+    "#;
+
+    // the interpreter stops instead of overflowing its stack
+    let (stdout, code) = interpret(source, "interpret-deep-recursion", "");
+    assert_eq!(stdout, "Result: 5000\n");
+    assert_eq!(code, 1);
+}
+
+#[test]
+fn define_in_a_branch_is_an_error() {
+    let source = r#"
+        (if 0 (define q 1) 0)
+        (printf (+ q 1))
+    "#;
+
+    // a binding must not depend on which branch runs
+    assert_eq!(
+        interpret(source, "interpret-define-in-branch", ""),
+        (String::new(), 1)
+    );
+    assert_eq!(
+        jit_run(source, "interpret-define-in-branch", ""),
+        (String::new(), 1)
+    );
+}
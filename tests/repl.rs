@@ -0,0 +1,43 @@
+mod common;
+
+use common::output_with_input;
+use std::process::Command;
+
+fn repl(stdin: &str) -> String {
+    let (stdout, code) = output_with_input(
+        Command::new(env!("CARGO_BIN_EXE_llvm-rust")).arg("repl"),
+        stdin,
+    );
+    assert_eq!(code, 0);
+    stdout
+}
+
+#[test]
+fn definitions_persist_across_entries() {
+    let stdin = "(define x 5)\n\
+                 (+ x 2.5)\n\
+                 (defn sq (n)\n  (* n n))\n\
+                 (sq 7)\n\
+                 (set! x (sq x))\n\
+                 (printf x)\n\
+                 (defn sq (n) (+ n 1))\n\
+                 (sq x)\n\
+                 (< x 3)\n";
+
+    assert_eq!(
+        repl(stdin),
+        "5\n7.500000\nLambda sq\n49\n25\nResult: 25\n25\nLambda sq\n26\n0\n"
+    );
+}
+
+#[test]
+fn errors_leave_the_session_usable() {
+    let stdin = "(defn half (n) (/ n 2))\n\
+                 (half 2.5)\n\
+                 (undefined 1)\n\
+                 (define y (input))\n\
+                 41\n\
+                 (+ (half 4) y)\n";
+
+    assert_eq!(repl(stdin), "Lambda half\n41\n43\n");
+}
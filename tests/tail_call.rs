@@ -0,0 +1,19 @@
+mod common;
+
+use common::{build_native, jit_run, stdout_of};
+
+#[test]
+fn self_recursion_in_tail_position_runs_in_constant_stack() {
+    let source = r#"
+        (defn count (n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+        (defn down (n) (cond ((< n 1) 7) (else (down (- n 1)))))
+        (printf (count 1000000 0))
+        (printf (down 1000000))
+        0
+    "#;
+    let expected = "Result: 1000000\nResult: 7\n";
+
+    assert_eq!(jit_run(source, "tail-call", ""), (expected.to_string(), 0));
+    let exe = build_native(source, "tail-call");
+    assert_eq!(stdout_of(&exe), expected);
+}